@@ -0,0 +1,280 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// On-disk format version of ~/.pconfig. Version 1 was a flat list of CL numbers.
+const CONFIG_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackedCl {
+    pub cl: String,
+    pub tracked_at: String, // local time, "YYYY/MM/DD hh:mm:ss"
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub note: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_shelved: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review: Option<String>, // local review registry, e.g. "approved", "needs-work"
+}
+
+/// Tracked changelists of one client on one server.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Workspace {
+    pub port: String,
+    pub client: String,
+    #[serde(default)]
+    pub changelists: Vec<TrackedCl>,
+}
+
+/// Team rules for changelist descriptions, shared by all workspaces. All off unless set in ~/.pconfig, e.g.
+/// "template": "<summary>\n\nBug: <bug number>", "max_summary_length": 80, "required_fields": ["Bug"].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DescriptionRules {
+    /// Description of new CLs. {branch}, {user} and {date} are filled in when the CL is created.
+    pub template: String,
+    /// Longest allowed first (summary) line, 0 for no limit
+    pub max_summary_length: usize,
+    /// "Name: value" lines every description must have, e.g. "Bug", "Test", "Reviewers"
+    pub required_fields: Vec<String>,
+}
+
+/// A local command `p submit` runs before submitting, e.g. a build or a test run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmitHook {
+    pub name: String,
+    /// Run with `sh -c` in the current directory; "{cl}" is replaced with the CL number
+    pub command: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Config {
+    version: u32,
+    #[serde(default)]
+    descriptions: DescriptionRules,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    submit_hooks: Vec<SubmitHook>,
+    /// Syntax highlighting in annotate and diff views; on unless set to false
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    syntax_highlighting: Option<bool>,
+    #[serde(default)]
+    workspaces: Vec<Workspace>,
+}
+
+fn get_config_path() -> Result<PathBuf> {
+    let home = std::env::var("HOME")?;
+    Ok(PathBuf::from(home).join(".pconfig"))
+}
+
+/// Current local time in the same format p4 uses for dates.
+pub fn now() -> String {
+    chrono::Local::now().format("%Y/%m/%d %H:%M:%S").to_string()
+}
+
+/// (P4PORT, client) of the current directory, looked up once per run.
+fn current_workspace_key() -> Result<(String, String)> {
+    static KEY: OnceLock<(String, String)> = OnceLock::new();
+    if let Some(key) = KEY.get() {
+        return Ok(key.clone());
+    }
+    let key = (crate::perforce::get_port()?, crate::perforce::get_current_client()?);
+    Ok(KEY.get_or_init(|| key).clone())
+}
+
+/// Read the config, migrating a version 1 flat file if needed.
+/// Returns the config and whether it was migrated (and so needs to be written back).
+fn read_config(config_path: &std::path::Path) -> Result<(Config, bool)> {
+    if !config_path.exists() {
+        return Ok((Config { version: CONFIG_VERSION, ..Default::default() }, false));
+    }
+
+    let content = std::fs::read_to_string(config_path)?;
+    if content.trim_start().starts_with('{') {
+        let config: Config = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", config_path.display()))?;
+        if config.version > CONFIG_VERSION {
+            anyhow::bail!("{} was written by a newer version of p (format version {})",
+                config_path.display(), config.version);
+        }
+        return Ok((config, false));
+    }
+
+    // Version 1: one CL per line, shared by all clients. Each CL moves to the workspace of the
+    // client that owns it on this server; CLs the server doesn't know are dropped.
+    // Keep the old file first, so nothing is lost if the migration goes wrong
+    let backup_path = config_path.with_extension("v1");
+    std::fs::copy(config_path, &backup_path)
+        .with_context(|| format!("Failed to back up {} to {}", config_path.display(), backup_path.display()))?;
+
+    let (port, client) = current_workspace_key()?;
+    let tracked_at = now();
+    let cls: Vec<String> = content
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect();
+    let statuses = crate::perforce::get_change_statuses(&cls)?;
+
+    // The current client's workspace comes first, then the others in the order their CLs appear
+    let mut workspaces = vec![Workspace { port: port.clone(), client, changelists: Vec::new() }];
+    let mut unknown = Vec::new();
+    for cl in cls {
+        let owner = match statuses.get(&cl) {
+            Some(crate::perforce::ChangeStatus::Pending { client: owner })
+            | Some(crate::perforce::ChangeStatus::Submitted { client: owner, .. }) => owner,
+            _ => {
+                unknown.push(cl);
+                continue;
+            }
+        };
+        let idx = match workspaces.iter().position(|w| &w.client == owner) {
+            Some(idx) => idx,
+            None => {
+                workspaces.push(Workspace { port: port.clone(), client: owner.clone(), changelists: Vec::new() });
+                workspaces.len() - 1
+            }
+        };
+        workspaces[idx].changelists.push(TrackedCl {
+            cl,
+            tracked_at: tracked_at.clone(),
+            note: String::new(),
+            tags: Vec::new(),
+            last_shelved: None,
+            review: None,
+        });
+    }
+    if !unknown.is_empty() {
+        eprintln!("Not tracking {} CL(s) unknown to the server: {}", unknown.len(), unknown.join(", "));
+        eprintln!("They are kept in {}", backup_path.display());
+    }
+
+    let config = Config {
+        version: CONFIG_VERSION,
+        descriptions: DescriptionRules::default(),
+        submit_hooks: Vec::new(),
+        syntax_highlighting: None,
+        workspaces,
+    };
+    Ok((config, true))
+}
+
+fn write_config(config_path: &std::path::Path, config: &Config) -> Result<()> {
+    // Write a temporary file and rename it, so readers never see a partial config
+    let tmp_path = config_path.with_extension("tmp");
+    {
+        let mut tmp = std::fs::File::create(&tmp_path)?;
+        tmp.write_all(serde_json::to_string_pretty(config)?.as_bytes())?;
+        tmp.write_all(b"\n")?;
+        tmp.sync_all()?;
+    }
+    std::fs::rename(&tmp_path, config_path)?;
+    Ok(())
+}
+
+/// Run `f` on the config while holding an exclusive lock on it, so concurrent `p` invocations don't
+/// clobber each other or read a half-migrated file. Writes the config back if `f` returns true.
+fn with_config<T>(f: impl FnOnce(&mut Config) -> (T, bool)) -> Result<T> {
+    let config_path = get_config_path()?;
+    let lock_file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(config_path.with_extension("lock"))?;
+    lock_file.lock().context("Failed to lock ~/.pconfig")?;
+
+    let (mut config, migrated) = read_config(&config_path)?;
+    let (result, modified) = f(&mut config);
+
+    if modified || migrated {
+        config.version = CONFIG_VERSION;
+        write_config(&config_path, &config)?;
+    }
+
+    // Lock is released when lock_file is dropped
+    Ok(result)
+}
+
+/// Run `f` on the current workspace's entry of the config. Writes the config back if `f` returns true.
+fn with_workspace<T>(f: impl FnOnce(&mut Workspace) -> (T, bool)) -> Result<T> {
+    let (port, client) = current_workspace_key()?;
+    with_config(|config| {
+        let idx = match config.workspaces.iter().position(|w| w.port == port && w.client == client) {
+            Some(idx) => idx,
+            None => {
+                config.workspaces.push(Workspace { port, client, changelists: Vec::new() });
+                config.workspaces.len() - 1
+            }
+        };
+
+        let (result, modified) = f(&mut config.workspaces[idx]);
+
+        // Don't leave empty entries behind for workspaces that were only looked at
+        config.workspaces.retain(|w| !w.changelists.is_empty());
+        (result, modified)
+    })
+}
+
+/// Get the tracked changelists of the current workspace.
+pub fn get_tracked_cls() -> Result<Vec<TrackedCl>> {
+    with_workspace(|ws| (ws.changelists.clone(), false))
+}
+
+/// Start tracking a changelist in the current workspace (no-op if already tracked).
+pub fn track_cl(cl: &str) -> Result<()> {
+    update_tracked_cl(cl, |_| {})
+}
+
+/// Stop tracking a changelist in the current workspace.
+pub fn untrack_cl(cl: &str) -> Result<()> {
+    with_workspace(|ws| {
+        let before = ws.changelists.len();
+        ws.changelists.retain(|t| t.cl != cl);
+        ((), ws.changelists.len() != before)
+    })
+}
+
+/// Modify a tracked changelist's metadata, tracking it first if needed.
+pub fn update_tracked_cl(cl: &str, f: impl FnOnce(&mut TrackedCl)) -> Result<()> {
+    with_workspace(|ws| {
+        let idx = match ws.changelists.iter().position(|t| t.cl == cl) {
+            Some(idx) => idx,
+            None => {
+                ws.changelists.push(TrackedCl {
+                    cl: cl.to_string(),
+                    tracked_at: now(),
+                    note: String::new(),
+                    tags: Vec::new(),
+                    last_shelved: None,
+                    review: None,
+                });
+                ws.changelists.len() - 1
+            }
+        };
+        f(&mut ws.changelists[idx]);
+        ((), true)
+    })
+}
+
+/// Record that a changelist was just shelved.
+pub fn mark_shelved(cl: &str) -> Result<()> {
+    update_tracked_cl(cl, |t| t.last_shelved = Some(now()))
+}
+
+/// Get the description template and validation rules (defaults if the config has none).
+pub fn get_description_rules() -> Result<DescriptionRules> {
+    with_config(|config| (config.descriptions.clone(), false))
+}
+
+/// Whether annotate and diff views may highlight syntax.
+pub fn get_syntax_highlighting() -> Result<bool> {
+    with_config(|config| (config.syntax_highlighting.unwrap_or(true), false))
+}
+
+/// Get the commands to run before submitting.
+pub fn get_submit_hooks() -> Result<Vec<SubmitHook>> {
+    with_config(|config| (config.submit_hooks.clone(), false))
+}
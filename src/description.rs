@@ -0,0 +1,106 @@
+use crate::config::DescriptionRules;
+use crate::{git, perforce};
+use regex::Regex;
+
+/// Fill in the {branch}, {user} and {date} placeholders of a description template.
+pub fn render_template(template: &str) -> String {
+    let mut description = template.to_string();
+    
+    // Only ask p4/git for values the template actually uses
+    if description.contains("{branch}") {
+        description = description.replace("{branch}", &current_branch());
+    }
+    if description.contains("{user}") {
+        let user = perforce::get_user().unwrap_or_default();
+        description = description.replace("{user}", &user);
+    }
+    if description.contains("{date}") {
+        let date = chrono::Local::now().format("%Y/%m/%d").to_string();
+        description = description.replace("{date}", &date);
+    }
+    
+    description
+}
+
+/// The branch being worked on: the git branch when run inside a ginit repository,
+/// otherwise the last component of the client's stream.
+fn current_branch() -> String {
+    if let Ok(Some(branch)) = git::get_current_branch() {
+        return branch;
+    }
+    match perforce::get_client_stream() {
+        Ok(Some(stream)) => stream.rsplit('/').next().unwrap_or(&stream).to_string(),
+        _ => String::new(),
+    }
+}
+
+/// A value that is nothing but a "<...>" placeholder, like p4's own "<enter description here>".
+fn is_placeholder(value: &str) -> bool {
+    let value = value.trim();
+    value.starts_with('<') && value.ends_with('>') && !value[1..value.len() - 1].contains(['<', '>'])
+}
+
+/// The "<...>" placeholder of the template left in `value`, if any.
+fn find_placeholder(value: &str, template: &str) -> Option<String> {
+    if is_placeholder(value) {
+        return Some(value.trim().to_string());
+    }
+    let placeholder_re = Regex::new(r"<[^<>\n]+>").unwrap();
+    let found = placeholder_re
+        .find_iter(template)
+        .map(|m| m.as_str())
+        .find(|placeholder| value.contains(placeholder))
+        .map(|placeholder| placeholder.to_string());
+    found
+}
+
+/// Check a description against the rules. Returns one message per missing or malformed section.
+pub fn validate(description: &str, rules: &DescriptionRules) -> Vec<String> {
+    let mut problems = Vec::new();
+    
+    if description.trim().is_empty() {
+        problems.push("Description is empty".to_string());
+        return problems;
+    }
+    
+    let summary = description.lines().next().unwrap_or("").trim();
+    let field_re = Regex::new(r"^\s*([A-Za-z][\w-]*)\s*:(.*)$").unwrap();
+    
+    if summary.is_empty() {
+        problems.push("First line must be a one-line summary".to_string());
+    } else if let Some(placeholder) = find_placeholder(summary, &rules.template) {
+        problems.push(format!("Summary still has the template placeholder '{}'", placeholder));
+    } else if let Some(field) = field_re
+        .captures(summary)
+        .map(|cap| cap[1].to_string())
+        .filter(|name| rules.required_fields.iter().any(|f| f.eq_ignore_ascii_case(name)))
+    {
+        problems.push(format!("First line must be a summary, not a '{}:' line", field));
+    } else if rules.max_summary_length > 0 && summary.chars().count() > rules.max_summary_length {
+        problems.push(format!(
+            "Summary is {} characters long (max {})",
+            summary.chars().count(),
+            rules.max_summary_length
+        ));
+    }
+    
+    for field in &rules.required_fields {
+        let values: Vec<String> = description
+            .lines()
+            .skip(1)
+            .filter_map(|line| field_re.captures(line))
+            .filter(|cap| cap[1].eq_ignore_ascii_case(field))
+            .map(|cap| cap[2].trim().to_string())
+            .collect();
+        
+        if values.is_empty() {
+            problems.push(format!("Missing '{}:' line", field));
+        } else if values.iter().all(|v| v.is_empty()) {
+            problems.push(format!("'{}:' line is empty", field));
+        } else if let Some(placeholder) = values.iter().find_map(|v| find_placeholder(v, &rules.template)) {
+            problems.push(format!("'{}:' line still has the template placeholder '{}'", field, placeholder));
+        }
+    }
+    
+    problems
+}
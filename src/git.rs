@@ -0,0 +1,146 @@
+use anyhow::{anyhow, Context, Result};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Run git with the given arguments and return stdout as String.
+fn run(args: &[&str]) -> Result<String> {
+    run_in(Path::new("."), args)
+}
+
+/// Run git in the repository at `dir` and return stdout as String.
+fn run_in(dir: &Path, args: &[&str]) -> Result<String> {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("Failed to execute: git {:?}", args))?;
+
+    if !out.status.success() {
+        let e = String::from_utf8_lossy(&out.stderr);
+        return Err(anyhow!("Command `git {args:?}` failed: {e}"));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).to_string())
+}
+
+/// Get the root commit of HEAD (the "original versions" commit created by ginit).
+/// Returns None if the repository has no commits yet.
+pub fn get_root_commit(dir: &Path) -> Result<Option<String>> {
+    match run_in(dir, &["rev-list", "--max-parents=0", "HEAD"]) {
+        Ok(stdout) => Ok(stdout.lines().last().map(|s| s.trim().to_string())),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Get commits made on top of the root commit, as "<hash> <subject>" lines.
+/// None of these exist in Perforce: ginit only commits the original versions.
+pub fn get_unpushed_commits(root: &str, rev: &str) -> Result<Vec<String>> {
+    let range = format!("{}..{}", root, rev);
+    let stdout = run(&["log", "--oneline", &range])?;
+    Ok(stdout.lines().map(|s| s.to_string()).collect())
+}
+
+/// Get the current branch name, or None on a detached HEAD.
+pub fn get_current_branch() -> Result<Option<String>> {
+    let stdout = run(&["rev-parse", "--abbrev-ref", "HEAD"])?;
+    let branch = stdout.trim();
+    if branch == "HEAD" || branch.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(branch.to_string()))
+    }
+}
+
+/// Get all local branch names.
+pub fn get_branches() -> Result<Vec<String>> {
+    let stdout = run(&["for-each-ref", "--format=%(refname:short)", "refs/heads"])?;
+    Ok(stdout
+        .lines()
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| s.trim().to_string())
+        .collect())
+}
+
+/// Get stash entries, as "stash@{N}: <message>" lines.
+pub fn get_stashes() -> Result<Vec<String>> {
+    let stdout = run(&["stash", "list"])?;
+    Ok(stdout.lines().map(|s| s.to_string()).collect())
+}
+
+/// Get the patch for a stash entry (e.g. "stash@{0}").
+pub fn get_stash_patch(stash: &str) -> Result<Vec<u8>> {
+    Ok(run(&["stash", "show", "-p", "--include-untracked", stash])
+        .or_else(|_| run(&["stash", "show", "-p", stash]))?
+        .into_bytes())
+}
+
+/// Get files in the working tree of `dir` that differ from HEAD, including untracked files that aren't
+/// ignored (paths relative to the repo root).
+pub fn get_modified_files(dir: &Path) -> Result<Vec<String>> {
+    let mut files: Vec<String> = Vec::new();
+    for args in [&["diff", "--name-only", "HEAD"][..], &["ls-files", "--others", "--exclude-standard"][..]] {
+        let stdout = run_in(dir, args)?;
+        files.extend(stdout.lines().filter(|s| !s.trim().is_empty()).map(|s| s.to_string()));
+    }
+    Ok(files)
+}
+
+/// Get the content of `path` (relative to the repo root) at commit `rev` in the repository at `dir`.
+pub fn get_file_at(dir: &Path, rev: &str, path: &str) -> Result<Vec<u8>> {
+    let spec = format!("{}:{}", rev, path);
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["show", &spec])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("Failed to execute: git show {}", spec))?;
+
+    if !out.status.success() {
+        let e = String::from_utf8_lossy(&out.stderr);
+        return Err(anyhow!("Command `git show {spec}` failed: {e}"));
+    }
+    Ok(out.stdout)
+}
+
+/// Write a bundle containing all refs (branches, tags and the latest stash) to `path`.
+pub fn create_bundle(path: &str) -> Result<()> {
+    run(&["bundle", "create", path, "--all"])?;
+    Ok(())
+}
+
+/// Initialize a new repository in `dir`.
+pub fn init(dir: &Path) -> Result<()> {
+    run_in(dir, &["init", "-q"])?;
+    Ok(())
+}
+
+/// Stage everything in `dir` and commit it.
+pub fn commit_all(dir: &Path, message: &str) -> Result<()> {
+    run_in(dir, &["add", "-A"])?;
+    run_in(dir, &["commit", "-q", "--allow-empty", "-m", message])?;
+    Ok(())
+}
+
+/// Get files in the working tree of `dir` that differ from `rev`, as (status, path) pairs.
+/// Status is git's letter: M(odified), A(dded), D(eleted), ...; untracked files are reported as A.
+pub fn get_changed_files(dir: &Path, rev: &str) -> Result<Vec<(String, String)>> {
+    let mut changes = Vec::new();
+    
+    let stdout = run_in(dir, &["diff", "--name-status", "--no-renames", rev])?;
+    for line in stdout.lines() {
+        if let Some((status, path)) = line.split_once('\t') {
+            changes.push((status.chars().take(1).collect(), path.to_string()));
+        }
+    }
+    
+    let stdout = run_in(dir, &["ls-files", "--others", "--exclude-standard"])?;
+    for path in stdout.lines().filter(|s| !s.trim().is_empty()) {
+        changes.push(("A".to_string(), path.to_string()));
+    }
+    
+    Ok(changes)
+}
@@ -0,0 +1,252 @@
+use owo_colors::OwoColorize;
+use std::sync::OnceLock;
+
+/// What the highlighter needs to know about a language. Lines are highlighted one at a time, so a
+/// block comment is only recognized on the line it starts on.
+pub struct Language {
+    extensions: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    keywords: &'static [&'static str],
+    preprocessor: bool, // "#include"-style directives
+    keys: bool,         // color "key:" like JSON/YAML object keys
+}
+
+const RUST: Language = Language {
+    extensions: &["rs"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"'], // ' also starts lifetimes
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false",
+        "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+        "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+    ],
+    preprocessor: false,
+    keys: false,
+};
+
+const C: Language = Language {
+    extensions: &["c", "h", "cc", "cpp", "cxx", "hh", "hpp", "hxx", "inl"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+    keywords: &[
+        "auto", "bool", "break", "case", "catch", "char", "class", "const", "constexpr", "continue", "default",
+        "delete", "do", "double", "else", "enum", "explicit", "extern", "false", "float", "for", "friend", "goto",
+        "if", "inline", "int", "long", "namespace", "new", "noexcept", "nullptr", "operator", "override",
+        "private", "protected", "public", "return", "short", "signed", "sizeof", "static", "struct", "switch",
+        "template", "this", "throw", "true", "try", "typedef", "typename", "union", "unsigned", "using",
+        "virtual", "void", "volatile", "while",
+    ],
+    preprocessor: true,
+    keys: false,
+};
+
+const PYTHON: Language = Language {
+    extensions: &["py"],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else",
+        "except", "False", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "None",
+        "nonlocal", "not", "or", "pass", "raise", "return", "self", "True", "try", "while", "with", "yield",
+    ],
+    preprocessor: false,
+    keys: false,
+};
+
+const JAVA: Language = Language {
+    extensions: &["java", "kt", "cs"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+    keywords: &[
+        "abstract", "boolean", "break", "byte", "case", "catch", "char", "class", "continue", "default", "do",
+        "double", "else", "enum", "extends", "false", "final", "finally", "float", "for", "if", "implements",
+        "import", "instanceof", "int", "interface", "long", "new", "null", "package", "private", "protected",
+        "public", "return", "short", "static", "super", "switch", "synchronized", "this", "throw", "throws",
+        "true", "try", "var", "void", "volatile", "while",
+    ],
+    preprocessor: false,
+    keys: false,
+};
+
+const GO: Language = Language {
+    extensions: &["go"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+    keywords: &[
+        "break", "case", "chan", "const", "continue", "default", "defer", "else", "fallthrough", "false", "for",
+        "func", "go", "goto", "if", "import", "interface", "map", "nil", "package", "range", "return", "select",
+        "struct", "switch", "true", "type", "var",
+    ],
+    preprocessor: false,
+    keys: false,
+};
+
+const SHELL: Language = Language {
+    extensions: &["sh", "bash", "zsh", "ksh"],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    keywords: &[
+        "case", "do", "done", "elif", "else", "esac", "exit", "export", "fi", "for", "function", "if", "in",
+        "local", "return", "then", "until", "while",
+    ],
+    preprocessor: false,
+    keys: false,
+};
+
+const JSON: Language = Language {
+    extensions: &["json"],
+    line_comments: &[],
+    block_comment: None,
+    quotes: &['"'],
+    keywords: &["true", "false", "null"],
+    preprocessor: false,
+    keys: true,
+};
+
+const YAML: Language = Language {
+    extensions: &["yaml", "yml"],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    keywords: &["true", "false", "null", "yes", "no"],
+    preprocessor: false,
+    keys: true,
+};
+
+const LANGUAGES: &[&Language] = &[&RUST, &C, &PYTHON, &JAVA, &GO, &SHELL, &JSON, &YAML];
+
+/// Whether to highlight at all: off for dumb terminals, with NO_COLOR set, or when turned off in ~/.pconfig.
+fn enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        let dumb_terminal = std::env::var("TERM").map(|t| t == "dumb").unwrap_or(false);
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        !dumb_terminal && !no_color && crate::config::get_syntax_highlighting().unwrap_or(true)
+    })
+}
+
+/// The language of a depot or local path (a trailing #rev or @change is ignored), or None when the
+/// file type is unknown or highlighting is turned off.
+pub fn language_for(path: &str) -> Option<&'static Language> {
+    if !enabled() {
+        return None;
+    }
+    let path = path.split(['#', '@']).next().unwrap_or(path);
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    let (_, extension) = file_name.rsplit_once('.')?;
+    let extension = extension.to_lowercase();
+    LANGUAGES.iter().copied().find(|lang| lang.extensions.contains(&extension.as_str()))
+}
+
+fn starts_with_at(chars: &[char], pos: usize, pattern: &str) -> bool {
+    (pos..).zip(pattern.chars()).all(|(idx, p)| chars.get(idx) == Some(&p))
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Whether the token ending at `pos` is a key: the next non-blank character is a ':'. Bare words
+/// also need a blank after it, so "http://" in a YAML value isn't a key.
+fn followed_by_colon(chars: &[char], pos: usize, need_blank: bool) -> bool {
+    let Some(colon) = (pos..chars.len()).find(|&idx| !chars[idx].is_whitespace()) else {
+        return false;
+    };
+    chars[colon] == ':' && (!need_blank || chars.get(colon + 1).map(|c| c.is_whitespace()).unwrap_or(true))
+}
+
+/// Color one line of source code.
+pub fn highlight(line: &str, lang: &Language) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+
+    if lang.preprocessor && line.trim_start().starts_with('#') {
+        return line.magenta().to_string();
+    }
+
+    while i < chars.len() {
+        let c = chars[i];
+        let at_word_start = i == 0 || chars[i - 1].is_whitespace();
+
+        // Comments run to the end of the line. '#' only starts one at a word start (not "$#" or "a#b").
+        if lang.line_comments.iter().any(|p| starts_with_at(&chars, i, p) && (!p.starts_with('#') || at_word_start)) {
+            let rest: String = chars[i..].iter().collect();
+            out.push_str(&rest.bright_black().to_string());
+            break;
+        }
+        if let Some((open, close)) = lang.block_comment {
+            if starts_with_at(&chars, i, open) {
+                let mut end = i + open.chars().count();
+                while end < chars.len() && !starts_with_at(&chars, end, close) {
+                    end += 1;
+                }
+                end = (end + close.chars().count()).min(chars.len());
+                let comment: String = chars[i..end].iter().collect();
+                out.push_str(&comment.bright_black().to_string());
+                i = end;
+                continue;
+            }
+        }
+
+        if lang.quotes.contains(&c) {
+            let mut end = i + 1;
+            while end < chars.len() && chars[end] != c {
+                if chars[end] == '\\' {
+                    end += 1;
+                }
+                end += 1;
+            }
+            end = (end + 1).min(chars.len());
+            let string: String = chars[i..end].iter().collect();
+            if lang.keys && followed_by_colon(&chars, end, false) {
+                out.push_str(&string.blue().to_string());
+            } else {
+                out.push_str(&string.green().to_string());
+            }
+            i = end;
+            continue;
+        }
+
+        if c.is_ascii_digit() && (i == 0 || !is_ident_char(chars[i - 1])) {
+            let mut end = i;
+            while end < chars.len() && (is_ident_char(chars[end]) || chars[end] == '.') {
+                end += 1;
+            }
+            let number: String = chars[i..end].iter().collect();
+            out.push_str(&number.cyan().to_string());
+            i = end;
+            continue;
+        }
+
+        if is_ident_char(c) {
+            let mut end = i;
+            while end < chars.len() && (is_ident_char(chars[end]) || (lang.keys && chars[end] == '-')) {
+                end += 1;
+            }
+            let word: String = chars[i..end].iter().collect();
+            if lang.keys && followed_by_colon(&chars, end, true) {
+                out.push_str(&word.blue().to_string());
+            } else if lang.keywords.contains(&word.as_str()) {
+                out.push_str(&word.magenta().to_string());
+            } else {
+                out.push_str(&word);
+            }
+            i = end;
+            continue;
+        }
+
+        out.push(c);
+        i += 1;
+    }
+
+    out
+}
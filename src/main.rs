@@ -1,6 +1,11 @@
+mod config;
+mod description;
+mod git;
+mod highlight;
+mod merge;
 mod perforce;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use itertools::Itertools;
 use owo_colors::OwoColorize;
@@ -35,7 +40,11 @@ enum Commands {
     /// Interactive file selector to reopen files to a different changelist.
     Reopen,
     /// Interactive file selector to revert files.
-    Revert,
+    Revert {
+        /// Only list files opened for edit whose content has not changed
+        #[arg(long)]
+        unchanged: bool,
+    },
     /// Unshelve files from a changelist.
     Unshelve,
     /// Shelve files in a changelist.
@@ -54,21 +63,147 @@ enum Commands {
         /// Path(s) to the file(s) to add (supports wildcards)
         files: Vec<String>,
     },
+    /// Create a new changelist, optionally opening files in it.
+    #[command(name = "new")]
+    New {
+        /// Description of the new changelist; repeat for more paragraphs (prompted for if not given)
+        #[arg(short, long)]
+        message: Vec<String>,
+        /// Path(s) to file(s) to open in the new changelist (supports wildcards)
+        files: Vec<String>,
+    },
+    /// Run pre-submit checks and submit a changelist.
+    #[command(name = "submit")]
+    Submit {
+        /// Changelist number (selected interactively if not given)
+        cl: Option<String>,
+    },
+    /// Show files changed without being opened and open them in a changelist.
+    #[command(name = "status")]
+    Status {
+        /// Directory or file pattern to check (default: the current directory)
+        path: Option<String>,
+    },
+    /// Preview and sync files, then resolve any files that need it.
+    #[command(name = "sync")]
+    Sync {
+        /// Files or paths to sync, with optional revisions (default: the whole client)
+        paths: Vec<String>,
+        /// Only show what would be synced
+        #[arg(short = 'n', long)]
+        preview: bool,
+    },
+    /// Resolve files interactively with a three-pane merge view.
+    #[command(name = "resolve")]
+    Resolve {
+        /// Only resolve these files (default: all files needing resolve)
+        files: Vec<String>,
+    },
     /// Initialize a git repository in the current directory.
     #[command(name = "ginit")]
-    Ginit,
+    Ginit {
+        /// Restore your changes from the journal left by an interrupted ginit
+        #[arg(long)]
+        recover: bool,
+    },
     /// Remove git repository but keep all files.
     #[command(name = "gdeinit")]
-    Gdeinit,
+    Gdeinit {
+        /// Save the .git directory into this archive (e.g., /tmp/git-backup.tar.gz) before removing it
+        #[arg(long, value_name = "PATH")]
+        archive_git: Option<String>,
+    },
+    /// Create a scratch git workspace from a shelved changelist, or shelve one back.
+    #[command(name = "scratch")]
+    Scratch {
+        /// Shelved changelist to materialize in a temporary directory
+        #[arg(required_unless_present = "shelve")]
+        cl: Option<String>,
+        /// Turn the changes in this scratch directory into a new shelved CL in your client
+        #[arg(long, value_name = "DIR", conflicts_with = "cl")]
+        shelve: Option<String>,
+    },
     /// Manage tracked changelists.
     #[command(name = "ls")]
-    Ls,
+    Ls {
+        /// Stop tracking CLs that were submitted, deleted or belong to another client
+        #[arg(long)]
+        prune: bool,
+    },
+    /// Track a changelist and set its note and tags.
+    #[command(name = "track")]
+    Track {
+        /// Changelist number
+        cl: String,
+        /// Note to attach to the changelist
+        #[arg(long)]
+        note: Option<String>,
+        /// Tag to add (can be repeated)
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        /// Tag to remove (can be repeated)
+        #[arg(long = "untag", value_name = "TAG")]
+        untags: Vec<String>,
+        /// Review status to record, e.g. "requested", "approved", "needs-work" ("none" to clear)
+        #[arg(long, value_name = "STATUS")]
+        review: Option<String>,
+    },
+    /// Edit only the description of a changelist and write it back.
+    #[command(name = "describe-edit")]
+    DescribeEdit {
+        /// Changelist number
+        cl: String,
+        /// Type the new description in the terminal instead of opening $EDITOR
+        #[arg(long)]
+        inline: bool,
+    },
     /// Show annotated file with CL, user, date, and line content.
     #[command(name = "annotate")]
     Annotate {
-        /// Path to the file to annotate
+        /// Depot or local path to annotate, optionally with #rev, @CL, @label, @yyyy/mm/dd or @=CL (shelved)
+        file: String,
+        /// Don't follow integrations (much faster on heavily integrated files)
+        #[arg(long)]
+        no_integrations: bool,
+        /// Ignore whitespace changes (p4 annotate -db)
+        #[arg(short = 'b', long, conflicts_with = "ignore_all_space")]
+        ignore_space_change: bool,
+        /// Ignore all whitespace (p4 annotate -dw)
+        #[arg(short = 'w', long)]
+        ignore_all_space: bool,
+        /// Print one tab-separated record per line instead of opening the viewer:
+        /// <line> <CL> <origin> <user> <date> <original line> <content>. Origin is submitted, shelved
+        /// or local, with CL "-" unless submitted; the original line is "-" when it isn't known
+        #[arg(long, conflicts_with = "json")]
+        porcelain: bool,
+        /// Print the annotation as a JSON object instead of opening the viewer
+        #[arg(long)]
+        json: bool,
+    },
+    /// Browse the revision history of a file, following branches and integrations.
+    #[command(name = "log")]
+    Log {
+        /// Depot or local path of the file
         file: String,
     },
+    /// Browse recent submitted changelists affecting a path, and cherry-pick them into a pending CL.
+    #[command(name = "changes")]
+    Changes {
+        /// Depot or local path (defaults to the current directory)
+        path: Option<String>,
+        /// Most changelists to load
+        #[arg(short = 'm', long, default_value_t = 100)]
+        max: usize,
+        /// Only changelists submitted by this user
+        #[arg(short = 'u', long)]
+        user: Option<String>,
+        /// Only changelists submitted on or after this date (yyyy/mm/dd)
+        #[arg(long)]
+        since: Option<String>,
+        /// Only changelists submitted up to and including this date (yyyy/mm/dd)
+        #[arg(long)]
+        until: Option<String>,
+    },
     /// Pack opened files into a compressed archive.
     #[command(name = "pack")]
     Pack {
@@ -90,16 +225,47 @@ fn main() -> Result<()> {
         Commands::Opened => cmd_opened()?,
         Commands::Change => cmd_change()?,
         Commands::Reopen => cmd_reopen()?,
-        Commands::Revert => cmd_revert()?,
+        Commands::Revert { unchanged } => cmd_revert(unchanged)?,
         Commands::Unshelve => cmd_unshelve()?,
         Commands::Shelve => cmd_shelve()?,
         Commands::Diff => cmd_diff()?,
         Commands::Open { files } => cmd_open(&files)?,
         Commands::Add { files } => cmd_add(&files)?,
-        Commands::Ginit => cmd_ginit()?,
-        Commands::Gdeinit => cmd_gdeinit()?,
-        Commands::Ls => cmd_ls()?,
-        Commands::Annotate { file } => cmd_annotate(&file)?,
+        Commands::New { message, files } => cmd_new(&message, &files)?,
+        Commands::Submit { cl } => cmd_submit(cl.as_deref())?,
+        Commands::Status { path } => cmd_status(path.as_deref())?,
+        Commands::Sync { paths, preview } => cmd_sync(&paths, preview)?,
+        Commands::Resolve { files } => cmd_resolve(&files)?,
+        Commands::Ginit { recover } => cmd_ginit(recover)?,
+        Commands::Gdeinit { archive_git } => cmd_gdeinit(archive_git.as_deref())?,
+        Commands::Scratch { cl, shelve } => match shelve {
+            Some(dir) => cmd_scratch_shelve(&dir)?,
+            None => cmd_scratch(cl.as_deref().unwrap_or_default())?,
+        },
+        Commands::Ls { prune } => cmd_ls(prune)?,
+        Commands::Track { cl, note, tags, untags, review } => {
+            cmd_track(&cl, note.as_deref(), &tags, &untags, review.as_deref())?
+        }
+        Commands::DescribeEdit { cl, inline } => cmd_describe_edit(&cl, inline)?,
+        Commands::Annotate { file, no_integrations, ignore_space_change, ignore_all_space, porcelain, json } => {
+            let options = perforce::AnnotateOptions {
+                follow_integrations: !no_integrations,
+                ignore_space_change,
+                ignore_all_space,
+            };
+            let output = if porcelain {
+                AnnotateOutput::Porcelain
+            } else if json {
+                AnnotateOutput::Json
+            } else {
+                AnnotateOutput::Viewer
+            };
+            cmd_annotate(&file, &options, output)?
+        }
+        Commands::Log { file } => cmd_log(&file)?,
+        Commands::Changes { path, max, user, since, until } => {
+            cmd_changes(path.as_deref(), max, user.as_deref(), since.as_deref(), until.as_deref())?
+        }
         Commands::Pack { output } => cmd_pack(&output)?,
         Commands::Unpack { input } => cmd_unpack(&input)?,
     }
@@ -295,15 +461,43 @@ fn cmd_change() -> Result<()> {
     println!("Select a changelist to edit:");
     println!();
     
-    // Run interactive selector
-    let selected = interactive_select_with_desc(&options, &cl_descriptions)?;
+    // Run interactive selector, coming back to it after editing a description
+    let mut selected_idx = 0;
+    let selected = loop {
+        match interactive_select_with_actions(&options, &cl_descriptions, &[('e', "edit the description")], selected_idx)? {
+            Some((cl, Some('e'))) => {
+                selected_idx = options.iter().position(|o| *o == cl).unwrap_or(0);
+                if cl == "default" || cl == "[Create new CL]" {
+                    println!("{}", "Only numbered changelists have a description to edit.".bright_yellow());
+                } else {
+                    match edit_description(&cl, false) {
+                        Ok(true) => {
+                            if let Ok(Some(desc)) = perforce::get_change_description(&cl) {
+                                cl_descriptions.insert(cl.clone(), desc.lines().next().unwrap_or("").trim().to_string());
+                            }
+                        }
+                        Ok(false) => {}
+                        Err(e) => println!("{} {}", "✗".bright_red(), e),
+                    }
+                }
+                println!();
+            }
+            Some((cl, _)) => break Some(cl),
+            None => break None,
+        }
+    };
     
     if let Some(selection) = selected {
         let cl = if selection == "[Create new CL]" {
             // Create a new changelist
-            println!("\nCreating new changelist...");
-            let new_cl = perforce::create_changelist()?;
-            add_tracked_cl(&new_cl)?;
+            println!();
+            let new_cl = match create_changelist_interactive(&[])? {
+                Some(cl) => cl,
+                None => {
+                    println!("Cancelled.");
+                    return Ok(());
+                }
+            };
             println!("{}", format!("✓ Created CL {}", new_cl).bright_green());
             println!();
             new_cl
@@ -402,7 +596,7 @@ fn cmd_reopen() -> Result<()> {
     println!();
     
     // Interactive file selector
-    let selected_files = interactive_file_select(&opened, &cl_to_color, &cl_descriptions, false)?;
+    let selected_files = interactive_file_select(&opened, &cl_to_color, &cl_descriptions, false, None)?;
     
     if selected_files.is_empty() {
         println!("No files selected.");
@@ -470,9 +664,13 @@ fn cmd_reopen() -> Result<()> {
             
             if input.is_empty() {
                 // Create new CL
-                println!("Creating new changelist...");
-                let new_cl = perforce::create_changelist()?;
-                add_tracked_cl(&new_cl)?;
+                let new_cl = match create_changelist_interactive(&[])? {
+                    Some(cl) => cl,
+                    None => {
+                        println!("Cancelled.");
+                        return Ok(());
+                    }
+                };
                 println!("Created CL {}", new_cl);
                 new_cl
             } else {
@@ -539,7 +737,7 @@ fn cmd_reopen() -> Result<()> {
     Ok(())
 }
 
-fn cmd_revert() -> Result<()> {
+fn cmd_revert(only_unchanged: bool) -> Result<()> {
     let mut opened = perforce::get_opened_files()?;
     
     if opened.is_empty() {
@@ -547,6 +745,27 @@ fn cmd_revert() -> Result<()> {
         return Ok(());
     }
     
+    // Files opened for edit with no content changes (p4 diff -sr). Without --unchanged they only
+    // mark the list, so a failing diff just means none are marked.
+    let unchanged = if only_unchanged {
+        perforce::get_unchanged_files()?
+    } else {
+        perforce::get_unchanged_files().unwrap_or_default()
+    };
+    // diff -sr also lists integrations, branches and move/adds without content changes: reverting
+    // those would drop the integration records or split a move, so only plain edits count
+    let unchanged: std::collections::HashSet<String> = unchanged
+        .into_iter()
+        .filter(|depot| opened.iter().any(|f| &f.depot_file == depot && f.action == "edit"))
+        .collect();
+    if only_unchanged {
+        opened.retain(|f| unchanged.contains(&f.depot_file));
+        if opened.is_empty() {
+            println!("No unchanged open files found.");
+            return Ok(());
+        }
+    }
+    
     // Sort files by changelist to group them together
     opened.sort_by(|a, b| {
         if a.changelist == "default" && b.changelist != "default" {
@@ -614,7 +833,7 @@ fn cmd_revert() -> Result<()> {
     println!();
     
     // Interactive file selector
-    let selected_files = interactive_file_select(&opened, &cl_to_color, &cl_descriptions, false)?;
+    let selected_files = interactive_file_select(&opened, &cl_to_color, &cl_descriptions, only_unchanged, Some(&unchanged))?;
     
     if selected_files.is_empty() {
         println!("No files selected.");
@@ -626,14 +845,26 @@ fn cmd_revert() -> Result<()> {
     for file in &selected_files {
         println!("  - {}", file.depot_file);
     }
-    println!("\nThis will discard all changes. Are you sure? (yes/no):");
-    
-    let mut confirm = String::new();
-    std::io::stdin().read_line(&mut confirm)?;
-    
-    if confirm.trim().to_lowercase() != "yes" {
-        println!("Cancelled.");
-        return Ok(());
+    if selected_files.iter().all(|f| unchanged.contains(&f.depot_file)) {
+        // Nothing to lose: the files match the revisions they were opened at
+        println!("\nNone of these files have content changes. Revert them? (y/n):");
+        
+        let mut confirm = String::new();
+        std::io::stdin().read_line(&mut confirm)?;
+        if !confirm.trim().eq_ignore_ascii_case("y") {
+            println!("Cancelled.");
+            return Ok(());
+        }
+    } else {
+        println!("\nThis will discard all changes. Are you sure? (yes/no):");
+        
+        let mut confirm = String::new();
+        std::io::stdin().read_line(&mut confirm)?;
+        
+        if confirm.trim().to_lowercase() != "yes" {
+            println!("Cancelled.");
+            return Ok(());
+        }
     }
     
     // Execute p4 revert for each selected file
@@ -728,13 +959,8 @@ fn cmd_diff() -> Result<()> {
     Ok(())
 }
 
-fn cmd_open(file_paths: &[String]) -> Result<()> {
-    if file_paths.is_empty() {
-        eprintln!("Error: No files specified");
-        return Ok(());
-    }
-    
-    // Collect all files (already expanded by shell, or use as-is)
+/// Expand wildcard arguments to the files they match, skipping anything that isn't a file.
+fn expand_file_args(file_paths: &[String]) -> Result<Vec<String>> {
     let mut files: Vec<String> = Vec::new();
     
     for file_path in file_paths {
@@ -766,17 +992,12 @@ fn cmd_open(file_paths: &[String]) -> Result<()> {
         }
     }
     
-    if files.is_empty() {
-        eprintln!("Error: No valid files found");
-        return Ok(());
-    }
-    
-    println!("Found {} file(s):", files.len());
-    for file in &files {
-        println!("  {}", file);
-    }
-    println!();
+    Ok(files)
+}
 
+/// Let the user pick a changelist (or create a new one) to put files in.
+/// Returns None if they cancelled.
+fn select_target_cl(prompt: &str) -> Result<Option<String>> {
     // Get all open changelists
     let opened = perforce::get_opened_files()?;
     
@@ -822,22 +1043,27 @@ fn cmd_open(file_paths: &[String]) -> Result<()> {
         }
     }
     
-    println!("Select a changelist to open the file(s) to:");
+    println!("{}", prompt);
     println!();
     
     let selected = match interactive_select_with_desc(&cls, &cl_descriptions)? {
         Some(cl) => cl,
         None => {
             println!("No changelist selected.");
-            return Ok(());
+            return Ok(None);
         }
     };
     
     let selected_cl = if selected == "[Create new CL]" {
         // Create a new changelist
-        println!("\nCreating new changelist...");
-        let new_cl = perforce::create_changelist()?;
-        add_tracked_cl(&new_cl)?;
+        println!();
+        let new_cl = match create_changelist_interactive(&[])? {
+            Some(cl) => cl,
+            None => {
+                println!("Cancelled.");
+                return Ok(None);
+            }
+        };
         println!("{}", format!("✓ Created CL {}", new_cl).bright_green());
         println!();
         new_cl
@@ -845,6 +1071,34 @@ fn cmd_open(file_paths: &[String]) -> Result<()> {
         selected
     };
     
+    Ok(Some(selected_cl))
+}
+
+fn cmd_open(file_paths: &[String]) -> Result<()> {
+    if file_paths.is_empty() {
+        eprintln!("Error: No files specified");
+        return Ok(());
+    }
+    
+    // Collect all files (already expanded by shell, or use as-is)
+    let files = expand_file_args(file_paths)?;
+    
+    if files.is_empty() {
+        eprintln!("Error: No valid files found");
+        return Ok(());
+    }
+    
+    println!("Found {} file(s):", files.len());
+    for file in &files {
+        println!("  {}", file);
+    }
+    println!();
+
+    let selected_cl = match select_target_cl("Select a changelist to open the file(s) to:")? {
+        Some(cl) => cl,
+        None => return Ok(()),
+    };
+    
     // Open all matching files
     let mut success_count = 0;
     let mut error_count = 0;
@@ -885,36 +1139,7 @@ fn cmd_add(file_paths: &[String]) -> Result<()> {
     }
     
     // Collect all files (already expanded by shell, or use as-is)
-    let mut files: Vec<String> = Vec::new();
-    
-    for file_path in file_paths {
-        // Check if it contains glob characters
-        if file_path.contains('*') || file_path.contains('?') || file_path.contains('[') {
-            // Try to expand glob pattern
-            let mut found_any = false;
-            for entry in glob(file_path)? {
-                match entry {
-                    Ok(path) => {
-                        if path.is_file() {
-                            files.push(path.to_string_lossy().to_string());
-                            found_any = true;
-                        }
-                    }
-                    Err(e) => eprintln!("Error reading glob entry: {}", e),
-                }
-            }
-            if !found_any {
-                eprintln!("Warning: No files match pattern '{}'", file_path);
-            }
-        } else {
-            // File path already provided (likely expanded by shell)
-            if std::path::Path::new(file_path).is_file() {
-                files.push(file_path.clone());
-            } else {
-                eprintln!("Warning: File '{}' does not exist or is not a file", file_path);
-            }
-        }
-    }
+    let files = expand_file_args(file_paths)?;
     
     if files.is_empty() {
         eprintln!("Error: No valid files found");
@@ -927,72 +1152,9 @@ fn cmd_add(file_paths: &[String]) -> Result<()> {
     }
     println!();
 
-    // Get all open changelists
-    let opened = perforce::get_opened_files()?;
-    
-    // Get unique CLs
-    let mut cls: Vec<String> = opened
-        .iter()
-        .map(|f| f.changelist.clone())
-        .collect::<std::collections::HashSet<_>>()
-        .into_iter()
-        .collect();
-    
-    // Sort: default first, then numeric
-    cls.sort_by(|a, b| {
-        if a == "default" && b != "default" {
-            std::cmp::Ordering::Less
-        } else if b == "default" && a != "default" {
-            std::cmp::Ordering::Greater
-        } else {
-            match (a.parse::<i64>(), b.parse::<i64>()) {
-                (Ok(x), Ok(y)) => x.cmp(&y),
-                _ => a.cmp(b),
-            }
-        }
-    });
-    
-    // Always include "default" if not already present
-    if !cls.contains(&"default".to_string()) {
-        cls.insert(0, "default".to_string());
-    }
-    
-    // Add "[Create new CL]" option at the beginning
-    cls.insert(0, "[Create new CL]".to_string());
-    
-    // Fetch descriptions for each CL
-    let mut cl_descriptions: HashMap<String, String> = HashMap::new();
-    cl_descriptions.insert("[Create new CL]".to_string(), "Create a new changelist".to_string());
-    for cl in &cls {
-        if cl != "default" && cl != "[Create new CL]" {
-            if let Ok(Some(desc)) = perforce::get_change_description(cl) {
-                let first_line = desc.lines().next().unwrap_or("").trim();
-                cl_descriptions.insert(cl.clone(), first_line.to_string());
-            }
-        }
-    }
-    
-    println!("Select a changelist to add the file(s) to:");
-    println!();
-    
-    let selected = match interactive_select_with_desc(&cls, &cl_descriptions)? {
+    let selected_cl = match select_target_cl("Select a changelist to add the file(s) to:")? {
         Some(cl) => cl,
-        None => {
-            println!("No changelist selected.");
-            return Ok(());
-        }
-    };
-    
-    let selected_cl = if selected == "[Create new CL]" {
-        // Create a new changelist
-        println!("\nCreating new changelist...");
-        let new_cl = perforce::create_changelist()?;
-        add_tracked_cl(&new_cl)?;
-        println!("{}", format!("✓ Created CL {}", new_cl).bright_green());
-        println!();
-        new_cl
-    } else {
-        selected
+        None => return Ok(()),
     };
     
     // Add all matching files
@@ -1176,7 +1338,13 @@ fn cmd_unshelve() -> Result<()> {
                         return Ok(());
                     }
                     Some(s) if s == "[Create new CL]" => {
-                        let new_cl = perforce::create_changelist()?;
+                        let new_cl = match create_changelist_interactive(&[])? {
+                            Some(cl) => cl,
+                            None => {
+                                println!("Cancelled.");
+                                return Ok(());
+                            }
+                        };
                         println!("Created new CL: {}", new_cl);
                         dest_cl = new_cl;
                     }
@@ -1208,7 +1376,7 @@ fn cmd_unshelve() -> Result<()> {
     let cl_descriptions: HashMap<String, String> = HashMap::new();
     
     // Interactive file selector - all files pre-selected
-    let selected_files = interactive_file_select(&shelved_files, &cl_to_color, &cl_descriptions, true)?;
+    let selected_files = interactive_file_select(&shelved_files, &cl_to_color, &cl_descriptions, true, None)?;
     
     if selected_files.is_empty() {
         println!("No files selected.");
@@ -1369,33 +1537,39 @@ fn cmd_shelve() -> Result<()> {
     
     println!("\nShelving {} file(s) from CL {}...", files.len(), selected_cl);
     
-    // Run p4 shelve -r -c <CL>
-    // The -r flag replaces all shelved files, removing files no longer in the CL
-    let output = std::process::Command::new("p4")
-        .arg("shelve")
-        .arg("-r")
-        .arg("-c")
-        .arg(&selected_cl)
-        .output()?;
-    
-    if output.status.success() {
-        add_tracked_cl(&selected_cl)?;
-        println!("\n{}", "✓ Successfully shelved files!".bright_green());
-        print!("{}", String::from_utf8_lossy(&output.stdout));
-    } else {
-        eprintln!("\n{}", "Error shelving files:".bright_red());
-        eprintln!("{}", String::from_utf8_lossy(&output.stderr));
-        return Err(anyhow::anyhow!("p4 shelve command failed"));
+    match perforce::shelve_changelist(&selected_cl) {
+        Ok(output) => {
+            config::mark_shelved(&selected_cl)?;
+            println!("\n{}", "✓ Successfully shelved files!".bright_green());
+            print!("{}", output);
+        }
+        Err(e) => {
+            eprintln!("\n{}", "Error shelving files:".bright_red());
+            eprintln!("{}", e);
+            return Err(anyhow::anyhow!("p4 shelve command failed"));
+        }
     }
 
     Ok(())
 }
 
-fn cmd_ginit() -> Result<()> {
+fn cmd_ginit(recover: bool) -> Result<()> {
     // Get current directory
     let current_dir = std::env::current_dir()?;
     let current_path = current_dir.display();
     
+    if recover {
+        return cmd_ginit_recover(&current_dir);
+    }
+    
+    // A new ginit would overwrite the journal, so recover it first
+    if ginit_journal_dir(&current_dir).exists() {
+        println!("{}", "An interrupted ginit left a journal of your changes.".bright_red());
+        println!("Path: {}", ginit_journal_dir(&current_dir).display());
+        println!("Run 'p ginit --recover' to restore them.");
+        return Ok(());
+    }
+    
     // Check if .git directory already exists
     let git_dir = current_dir.join(".git");
     if git_dir.exists() {
@@ -1482,8 +1656,10 @@ fn cmd_ginit() -> Result<()> {
         return Ok(());
     }
     
-    // Step 1: Save current working versions (with P4 changes)
+    // Step 1: Save current working versions (with P4 changes) to the journal on disk,
+    // so they survive a crash or failure while the files are swapped
     println!("\n{}", "Step 1: Saving current file versions...".bright_cyan());
+    let journal_dir = ginit_journal_dir(&current_dir);
     let mut saved_contents: Vec<(String, Vec<u8>)> = Vec::new();
     for (local_path, _, _) in &files_info {
         if let Ok(content) = std::fs::read(local_path) {
@@ -1491,10 +1667,51 @@ fn cmd_ginit() -> Result<()> {
             println!("  {} {}", "✓".bright_green(), local_path);
         }
     }
+    if let Err(e) = write_ginit_journal(&journal_dir, &saved_contents) {
+        // Nothing has been overwritten yet, so it's safe to stop here
+        std::fs::remove_dir_all(&journal_dir).ok();
+        eprintln!("\n{}", "Error saving file versions to the journal:".bright_red());
+        return Err(e);
+    }
+    println!("  Journal: {}", journal_dir.display().to_string().bright_black());
+    
+    let swap_result = commit_original_versions(&files_info);
+    
+    // Step 4: Restore current working versions (with changes), even if step 2 or 3 failed
+    println!("\n{}", "Step 4: Restoring your current changes...".bright_cyan());
+    if let Err(e) = restore_from_ginit_journal(&journal_dir) {
+        eprintln!("\n{}", "Error restoring your changes:".bright_red());
+        eprintln!("{}", e);
+        eprintln!("Your changes are kept in {}", journal_dir.display());
+        eprintln!("Run 'p ginit --recover' to try again.");
+        return Err(e);
+    }
+    swap_result?;
+    
+    // Show git status
+    println!("\n{}", "Git status:".bright_cyan());
+    let status_output = std::process::Command::new("git")
+        .arg("status")
+        .arg("--short")
+        .output()?;
+    
+    if status_output.status.success() {
+        print!("{}", String::from_utf8_lossy(&status_output.stdout));
+    }
+    
+    println!("\n{}", format!("✓ Complete! {} file(s) ready with your changes", files_info.len()).bright_green());
+    println!("{}", "  Initial commit contains the original Perforce versions".bright_blue());
+    println!("{}", "  Your changes are unstaged - use 'git diff' to see them".bright_blue());
     
+    Ok(())
+}
+
+/// Overwrite the opened files with their Perforce versions and commit them as ginit's initial commit.
+/// The caller saves the working versions to the journal first and restores them afterwards.
+fn commit_original_versions(files_info: &[(String, String, Option<String>)]) -> Result<()> {
     // Step 2: Restore original versions from Perforce
     println!("\n{}", "Step 2: Restoring original file versions from Perforce...".bright_cyan());
-    for (local_path, depot_path, workrev) in &files_info {
+    for (local_path, depot_path, workrev) in files_info {
         // Construct the depot path with revision
         let depot_with_rev = if let Some(rev) = workrev {
             format!("{}#{}", depot_path, rev)
@@ -1510,7 +1727,7 @@ fn cmd_ginit() -> Result<()> {
             .output()?;
         
         if output.status.success() {
-            std::fs::write(local_path, &output.stdout)?;
+            write_file_atomic(local_path, &output.stdout)?;
             println!("  {} {}", "✓".bright_green(), local_path);
         } else {
             eprintln!("  {} {} - {}", "✗".bright_red(), local_path,
@@ -1520,7 +1737,7 @@ fn cmd_ginit() -> Result<()> {
     
     // Step 3: Stage original versions and create initial commit
     println!("\n{}", "Step 3: Creating initial commit with original versions...".bright_cyan());
-    for (local_path, _, _) in &files_info {
+    for (local_path, _, _) in files_info {
         std::process::Command::new("git")
             .arg("add")
             .arg(local_path)
@@ -1540,32 +1757,288 @@ fn cmd_ginit() -> Result<()> {
             String::from_utf8_lossy(&commit_output.stderr).trim());
     }
     
-    // Step 4: Restore current working versions (with changes)
-    println!("\n{}", "Step 4: Restoring your current changes...".bright_cyan());
-    for (local_path, content) in &saved_contents {
-        std::fs::write(local_path, content)?;
-        println!("  {} {}", "✓".bright_green(), local_path);
-    }
-    
-    // Show git status
-    println!("\n{}", "Git status:".bright_cyan());
-    let status_output = std::process::Command::new("git")
-        .arg("status")
-        .arg("--short")
-        .output()?;
-    
-    if status_output.status.success() {
-        print!("{}", String::from_utf8_lossy(&status_output.stdout));
+    Ok(())
+}
+
+fn cmd_ginit_recover(current_dir: &std::path::Path) -> Result<()> {
+    let journal_dir = ginit_journal_dir(current_dir);
+    if !journal_dir.exists() {
+        println!("{}", "No ginit journal found in this directory, nothing to recover.".bright_yellow());
+        return Ok(());
     }
     
-    println!("\n{}", format!("✓ Complete! {} file(s) ready with your changes", files_info.len()).bright_green());
-    println!("{}", "  Initial commit contains the original Perforce versions".bright_blue());
-    println!("{}", "  Your changes are unstaged - use 'git diff' to see them".bright_blue());
+    println!("Restoring your changes from {}...", journal_dir.display().to_string().bright_cyan());
+    let restored = restore_from_ginit_journal(&journal_dir)?;
+    println!("\n{}", format!("✓ Recovered {} file(s)", restored).bright_green());
+    Ok(())
+}
+
+/// Outside .git, so that neither a broken repository nor 'p gdeinit' takes the journal with it.
+fn ginit_journal_dir(current_dir: &std::path::Path) -> std::path::PathBuf {
+    current_dir.join(".p-ginit-journal")
+}
+
+/// Write a file via a temporary file and rename, so it is never left half-written.
+fn write_file_atomic(path: &str, content: &[u8]) -> Result<()> {
+    let target = std::path::Path::new(path);
+    let file_name = target.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let tmp_path = target.with_file_name(format!(".{}.p-tmp", file_name));
+    
+    {
+        let mut tmp = std::fs::File::create(&tmp_path)?;
+        tmp.write_all(content)?;
+        tmp.sync_all()?;
+    }
+    
+    // Keep the original permissions (Perforce marks unopened files read-only)
+    if let Ok(metadata) = std::fs::metadata(target) {
+        std::fs::set_permissions(&tmp_path, metadata.permissions())?;
+    }
+    
+    if let Err(e) = std::fs::rename(&tmp_path, target) {
+        std::fs::remove_file(&tmp_path).ok();
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+// Journal layout: one "<N>.orig" file per saved file, plus a "manifest" of "<N>\t<local path>" lines.
+// The manifest is written last, so a journal without one was never complete and no file was touched.
+fn write_ginit_journal(journal_dir: &std::path::Path, files: &[(String, Vec<u8>)]) -> Result<()> {
+    std::fs::create_dir_all(journal_dir)?;
+    
+    let mut manifest = String::new();
+    for (idx, (local_path, content)) in files.iter().enumerate() {
+        let mut f = std::fs::File::create(journal_dir.join(format!("{}.orig", idx)))?;
+        f.write_all(content)?;
+        f.sync_all()?;
+        manifest.push_str(&format!("{}\t{}\n", idx, local_path));
+    }
+    
+    write_file_atomic(&journal_dir.join("manifest").to_string_lossy(), manifest.as_bytes())?;
+    Ok(())
+}
+
+fn read_ginit_journal(journal_dir: &std::path::Path) -> Result<Vec<(String, Vec<u8>)>> {
+    let manifest_path = journal_dir.join("manifest");
+    if !manifest_path.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let manifest = std::fs::read_to_string(&manifest_path)?;
+    let mut files = Vec::new();
+    for line in manifest.lines().filter(|l| !l.trim().is_empty()) {
+        let (idx, local_path) = line
+            .split_once('\t')
+            .ok_or_else(|| anyhow::anyhow!("Malformed journal manifest line: {}", line))?;
+        let content = std::fs::read(journal_dir.join(format!("{}.orig", idx)))?;
+        files.push((local_path.to_string(), content));
+    }
+    Ok(files)
+}
+
+/// Put every journaled file back, verify it is byte-identical, then clear the journal.
+/// The journal is kept if any file could not be restored.
+fn restore_from_ginit_journal(journal_dir: &std::path::Path) -> Result<usize> {
+    let saved_contents = read_ginit_journal(journal_dir)?;
+    
+    let mut failed = 0;
+    for (local_path, content) in &saved_contents {
+        let restored = write_file_atomic(local_path, content)
+            .and_then(|_| Ok(std::fs::read(local_path)?));
+        match restored {
+            Ok(ref on_disk) if on_disk == content => {
+                println!("  {} {}", "✓".bright_green(), local_path);
+            }
+            Ok(_) => {
+                eprintln!("  {} {} - content differs after restore", "✗".bright_red(), local_path);
+                failed += 1;
+            }
+            Err(e) => {
+                eprintln!("  {} {} - {}", "✗".bright_red(), local_path, e);
+                failed += 1;
+            }
+        }
+    }
+    
+    if failed > 0 {
+        anyhow::bail!("{} file(s) could not be restored byte-identical", failed);
+    }
+    
+    std::fs::remove_dir_all(journal_dir)?;
+    Ok(saved_contents.len())
+}
+
+// Work in a ginit repository that only exists in git and would be lost with .git
+struct UnpushedWork {
+    commits: Vec<(String, Vec<String>)>, // (branch, commits on top of the ginit commit)
+    stashes: Vec<String>,
+    local_only_files: Vec<String>, // differ from git HEAD and not opened or shelved in Perforce
+}
+
+impl UnpushedWork {
+    fn is_empty(&self) -> bool {
+        self.commits.is_empty() && self.stashes.is_empty() && self.local_only_files.is_empty()
+    }
+}
+
+fn find_unpushed_work(current_dir: &std::path::Path) -> Result<UnpushedWork> {
+    let mut work = UnpushedWork {
+        commits: Vec::new(),
+        stashes: Vec::new(),
+        local_only_files: Vec::new(),
+    };
+    
+    // Without any commit there is nothing git-only to lose
+    let root = match git::get_root_commit(current_dir)? {
+        Some(root) => root,
+        None => return Ok(work),
+    };
+    
+    // Commits on any branch beyond the initial ginit commit were never pushed to Perforce
+    let mut refs = git::get_branches()?;
+    if git::get_current_branch()?.is_none() {
+        refs.insert(0, "HEAD".to_string());
+    }
+    for branch in refs {
+        let commits = git::get_unpushed_commits(&root, &branch)?;
+        if !commits.is_empty() {
+            work.commits.push((branch, commits));
+        }
+    }
+    
+    work.stashes = git::get_stashes()?;
+    
+    // Opened files by canonical local path, so they compare equal to the paths git reports
+    let canonical = |path: &std::path::Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let opened = perforce::get_opened_files().context("Failed to list opened files")?;
+    let mut opened_paths: HashMap<std::path::PathBuf, &perforce::OpenedFile> = HashMap::new();
+    for file in &opened {
+        if let Some(local_path) = perforce::get_local_path(&file.depot_file)? {
+            opened_paths.insert(canonical(std::path::Path::new(&local_path)), file);
+        }
+    }
+    
+    // Files shelved in each CL: an opened file whose shelved version matches the disk is safe in Perforce
+    let mut shelved: HashMap<String, std::collections::HashSet<String>> = HashMap::new();
+    
+    // Files changed since git HEAD (or untracked) whose content Perforce doesn't have: not opened at
+    // all, or opened with local edits that aren't in a shelf
+    for rel_path in git::get_modified_files(current_dir)? {
+        let path = canonical(&current_dir.join(&rel_path));
+        let in_perforce = match opened_paths.get(&path) {
+            None => false,
+            Some(file) if file.action.contains("delete") => true, // Nothing on disk to lose
+            Some(file) if file.changelist == "default" => false,
+            Some(file) => {
+                if !shelved.contains_key(&file.changelist) {
+                    let files = perforce::get_shelved_files(&file.changelist).unwrap_or_default();
+                    shelved.insert(file.changelist.clone(), files.into_iter().map(|f| f.depot_file).collect());
+                }
+                shelved[&file.changelist].contains(&file.depot_file)
+                    && std::fs::read(&path).ok()
+                        == perforce::print_file(&format!("{}@={}", file.depot_file, file.changelist)).ok()
+            }
+        };
+        if !in_perforce {
+            work.local_only_files.push(path.to_string_lossy().to_string());
+        }
+    }
     
+    Ok(work)
+}
+
+fn print_unpushed_work(work: &UnpushedWork) {
+    if !work.commits.is_empty() {
+        println!("{}", "Commits not pushed back to Perforce:".bright_yellow().bold());
+        for (branch, commits) in &work.commits {
+            println!("  {} ({} commit(s))", branch.bright_cyan(), commits.len());
+            for commit in commits {
+                println!("    {}", commit);
+            }
+        }
+        println!();
+    }
+    
+    if !work.stashes.is_empty() {
+        println!("{}", "Stashes:".bright_yellow().bold());
+        for stash in &work.stashes {
+            println!("  {}", stash);
+        }
+        println!();
+    }
+    
+    if !work.local_only_files.is_empty() {
+        println!("{}", "Local changes not in git HEAD or in Perforce (not opened, or not shelved):".bright_yellow().bold());
+        for file in &work.local_only_files {
+            println!("  {}", file);
+        }
+        println!();
+    }
+}
+
+fn pack_unpushed_work(output_path: &str, current_dir: &std::path::Path, work: &UnpushedWork) -> Result<()> {
+    println!("\nCreating archive at '{}'...", output_path);
+    
+    let tar_gz_file = std::fs::File::create(output_path)?;
+    let enc = GzEncoder::new(tar_gz_file, Compression::default());
+    let mut tar = Builder::new(enc);
+    
+    // Git data goes under <dir>/.p-gdeinit so that 'p unpack' restores it next to the files
+    let backup_dir = current_dir.join(".p-gdeinit").to_string_lossy().trim_start_matches('/').to_string();
+    
+    if !work.commits.is_empty() || !work.stashes.is_empty() {
+        let bundle_path = std::env::temp_dir().join(format!("p-gdeinit-{}.bundle", std::process::id()));
+        let bundle_str = bundle_path.to_string_lossy().to_string();
+        git::create_bundle(&bundle_str)?;
+        let result = tar.append_path_with_name(&bundle_path, format!("{}/repo.bundle", backup_dir));
+        std::fs::remove_file(&bundle_path).ok();
+        result?;
+        println!("{} repo.bundle (all branches)", "✓".bright_green());
+    }
+    
+    for (idx, stash) in work.stashes.iter().enumerate() {
+        let stash_ref = format!("stash@{{{}}}", idx);
+        let patch = git::get_stash_patch(&stash_ref)?;
+        
+        let mut header = tar::Header::new_gnu();
+        header.set_size(patch.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, format!("{}/stash-{}.patch", backup_dir, idx), patch.as_slice())?;
+        println!("{} {}", "✓".bright_green(), stash);
+    }
+    
+    for local_path in &work.local_only_files {
+        if std::path::Path::new(local_path).exists() {
+            tar.append_path_with_name(local_path, local_path.trim_start_matches('/'))?;
+            println!("{} {}", "✓".bright_green(), local_path);
+        }
+    }
+    
+    tar.finish()?;
+    
+    println!("\n{}", format!("✓ Unpushed work saved to: {}", output_path).bright_green());
+    println!("{}", "  Restore with 'p unpack', then 'git clone .p-gdeinit/repo.bundle'".bright_blue());
+    Ok(())
+}
+
+fn archive_git_dir(output_path: &str, git_dir: &std::path::Path) -> Result<()> {
+    println!("\nArchiving {} to '{}'...", git_dir.display(), output_path);
+    
+    let tar_gz_file = std::fs::File::create(output_path)?;
+    let enc = GzEncoder::new(tar_gz_file, Compression::default());
+    let mut tar = Builder::new(enc);
+    
+    let archive_path = git_dir.to_string_lossy().trim_start_matches('/').to_string();
+    tar.append_dir_all(&archive_path, git_dir)?;
+    tar.finish()?;
+    
+    println!("{}", format!("✓ Git repository archived to: {}", output_path).bright_green());
     Ok(())
 }
 
-fn cmd_gdeinit() -> Result<()> {
+fn cmd_gdeinit(archive_git: Option<&str>) -> Result<()> {
     // Get current directory
     let current_dir = std::env::current_dir()?;
     let current_path = current_dir.display();
@@ -1578,10 +2051,30 @@ fn cmd_gdeinit() -> Result<()> {
         return Ok(());
     }
     
+    // Look for work that would be lost together with the .git directory
+    println!("Checking for work not pushed back to Perforce...");
+    let work = find_unpushed_work(&current_dir)?;
+    println!();
+    if !work.is_empty() {
+        print_unpushed_work(&work);
+    }
+    
     // Ask for confirmation
     println!("{}", "⚠️  WARNING: This will remove the git repository!".bright_red().bold());
     println!("Directory: {}", current_path.to_string().bright_cyan());
-    println!("All files will be kept, only the .git directory will be removed.");
+    if let Some(path) = archive_git {
+        println!("The .git directory will be archived to {} and then removed.", path.bright_cyan());
+    } else {
+        println!("All files will be kept, only the .git directory will be removed.");
+    }
+    if archive_git.is_none() {
+        if !work.commits.is_empty() || !work.stashes.is_empty() {
+            println!("{}", "The commits and stashes listed above will be lost.".bright_red());
+        }
+        if !work.local_only_files.is_empty() {
+            println!("{}", "The local-only files listed above stay on disk, but their git history will be lost.".bright_yellow());
+        }
+    }
     println!("\nAre you sure? (y/n):");
     
     let mut input = String::new();
@@ -1593,6 +2086,24 @@ fn cmd_gdeinit() -> Result<()> {
         return Ok(());
     }
     
+    if let Some(path) = archive_git {
+        // Keep the whole repository as an archive
+        archive_git_dir(path, &git_dir)?;
+    } else if !work.is_empty() {
+        // Offer to save just the unpushed work
+        println!("\nPack unpushed work into an archive first? Enter archive path (or press Enter to skip):");
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        let output_path = input.trim();
+        
+        if !output_path.is_empty() {
+            if let Err(e) = pack_unpushed_work(output_path, &current_dir, &work) {
+                eprintln!("\n{}", "Error packing unpushed work, git repository was not removed:".bright_red());
+                return Err(e);
+            }
+        }
+    }
+    
     // Remove .git directory
     match std::fs::remove_dir_all(&git_dir) {
         Ok(_) => {
@@ -1609,10 +2120,329 @@ fn cmd_gdeinit() -> Result<()> {
     Ok(())
 }
 
-fn cmd_ls() -> Result<()> {
+// Scratch workspaces mirror depot paths below the scratch directory: //depot/a/b.c -> <dir>/depot/a/b.c
+fn scratch_file_path(scratch_dir: &std::path::Path, depot_file: &str) -> std::path::PathBuf {
+    scratch_dir.join(depot_file.trim_start_matches("//"))
+}
+
+fn scratch_info_path(scratch_dir: &std::path::Path) -> std::path::PathBuf {
+    scratch_dir.join(".git").join("p-scratch")
+}
+
+fn cmd_scratch(cl_number: &str) -> Result<()> {
+    if cl_number.parse::<i64>().is_err() {
+        println!("Error: Invalid CL number '{}'", cl_number);
+        return Ok(());
+    }
+    
+    let shelved_files = perforce::get_shelved_files(cl_number)?;
+    if shelved_files.is_empty() {
+        println!("No shelved files found in CL {}", cl_number);
+        return Ok(());
+    }
+    
+    if let Ok(Some(desc)) = perforce::get_change_description(cl_number) {
+        let first_line = desc.lines().next().unwrap_or("").trim();
+        println!("CL {} - {}", cl_number.bright_cyan(), first_line);
+    }
+    
+    let scratch_dir = std::env::temp_dir().join(format!("p-scratch-{}", cl_number));
+    if scratch_dir.exists() {
+        println!("{}", "A scratch workspace for this CL already exists.".bright_yellow());
+        println!("Path: {}", scratch_dir.display());
+        return Ok(());
+    }
+    std::fs::create_dir_all(&scratch_dir)?;
+    git::init(&scratch_dir)?;
+    
+    // Step 1: Base versions - our have revision, or the revision the shelf is based on
+    println!("\n{}", "Step 1: Fetching have revisions...".bright_cyan());
+    for file in &shelved_files {
+        if matches!(file.action.as_str(), "add" | "branch" | "move/add") {
+            continue;
+        }
+        
+        let content = perforce::print_file(&format!("{}#have", file.depot_file)).or_else(|e| match &file.workrev {
+            Some(rev) => perforce::print_file(&format!("{}#{}", file.depot_file, rev)),
+            None => Err(e),
+        });
+        
+        match content {
+            Ok(content) => {
+                let path = scratch_file_path(&scratch_dir, &file.depot_file);
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&path, content)?;
+                println!("  {} {}", "✓".bright_green(), file.depot_file);
+            }
+            Err(e) => {
+                eprintln!("  {} {} - {}", "✗".bright_red(), file.depot_file, e);
+            }
+        }
+    }
+    
+    git::commit_all(&scratch_dir, &format!("Base: have revisions for CL {}", cl_number))?;
+    println!("{}", "✓ Base commit created".bright_green());
+    
+    // Step 2: Shelved content on top, left unstaged like ginit does with opened files
+    println!("\n{}", format!("Step 2: Applying shelved content from CL {}...", cl_number).bright_cyan());
+    for file in &shelved_files {
+        let path = scratch_file_path(&scratch_dir, &file.depot_file);
+        
+        if matches!(file.action.as_str(), "delete" | "move/delete") {
+            std::fs::remove_file(&path).ok();
+            println!("  {} {} (deleted)", "✓".bright_green(), file.depot_file);
+            continue;
+        }
+        
+        match perforce::print_file(&format!("{}@={}", file.depot_file, cl_number)) {
+            Ok(content) => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&path, content)?;
+                println!("  {} {}", "✓".bright_green(), file.depot_file);
+            }
+            Err(e) => {
+                eprintln!("  {} {} - {}", "✗".bright_red(), file.depot_file, e);
+            }
+        }
+    }
+    
+    std::fs::write(scratch_info_path(&scratch_dir), format!("{}\n", cl_number))?;
+    
+    println!("\n{}", format!("✓ Scratch workspace ready: {}", scratch_dir.display()).bright_green());
+    println!("{}", "  Base commit contains the have revisions".bright_blue());
+    println!("{}", format!("  CL {}'s shelved changes are unstaged - use 'git diff' to see them", cl_number).bright_blue());
+    println!("{}", format!("  Shelve your result with 'p scratch --shelve {}'", scratch_dir.display()).bright_blue());
+    
+    Ok(())
+}
+
+fn cmd_scratch_shelve(dir: &str) -> Result<()> {
+    let scratch_dir = std::fs::canonicalize(dir)?;
+    let info_path = scratch_info_path(&scratch_dir);
+    if !info_path.exists() {
+        println!("{}", "Not a scratch workspace (created by 'p scratch').".bright_yellow());
+        println!("Path: {}", scratch_dir.display());
+        return Ok(());
+    }
+    let source_cl = std::fs::read_to_string(&info_path)?.trim().to_string();
+    
+    let root = match git::get_root_commit(&scratch_dir)? {
+        Some(root) => root,
+        None => anyhow::bail!("Scratch workspace has no base commit"),
+    };
+    
+    // Everything that differs from the base commit goes into the new CL
+    let changes: Vec<(String, String)> = git::get_changed_files(&scratch_dir, &root)?
+        .into_iter()
+        .map(|(status, path)| (status, format!("//{}", path)))
+        .collect();
+    
+    if changes.is_empty() {
+        println!("No changes in scratch workspace {}", scratch_dir.display());
+        return Ok(());
+    }
+    
+    // Files need to be opened in our client to shelve them, so they must not be opened already
+    let opened = perforce::get_opened_files()?;
+    let opened_paths: std::collections::HashSet<String> = opened.iter().map(|f| f.depot_file.clone()).collect();
+    let conflicts: Vec<_> = changes.iter().filter(|(_, depot)| opened_paths.contains(depot)).collect();
+    if !conflicts.is_empty() {
+        println!("{}", "These files are already opened in your client:".bright_red());
+        for (_, depot) in &conflicts {
+            println!("  {}", depot);
+        }
+        println!("Revert or shelve them first, then try again.");
+        return Ok(());
+    }
+    
+    // The files are briefly opened in our workspace to shelve them, and reverted afterwards. Make sure
+    // that can't lose anything: no file where an add goes, and edited or deleted files synced to the
+    // scratch base without local changes, so the revert puts back exactly what is there now.
+    println!("Checking your workspace...");
+    let mut plan: Vec<(&str, &str, String, std::path::PathBuf)> = Vec::new(); // (status, depot, local, scratch)
+    let mut problems: Vec<String> = Vec::new();
+    for (status, depot) in &changes {
+        let Some(local_path) = perforce::get_local_path(depot)? else {
+            problems.push(format!("{}: not in client view", depot));
+            continue;
+        };
+        let local_exists = std::path::Path::new(&local_path).exists();
+        if status == "A" {
+            if local_exists {
+                problems.push(format!("{}: {} already exists in your workspace", depot, local_path));
+            }
+        } else {
+            let base = git::get_file_at(&scratch_dir, &root, depot.trim_start_matches('/'))?;
+            match perforce::print_file(&format!("{}#have", depot)) {
+                Err(_) => problems.push(format!("{}: not synced in your workspace", depot)),
+                Ok(have) if have != base => {
+                    problems.push(format!("{}: your have revision differs from the scratch base, sync to it first", depot));
+                }
+                Ok(have) => {
+                    if !local_exists || std::fs::read(&local_path)? != have {
+                        problems.push(format!("{}: has local changes that are not opened in Perforce", depot));
+                    }
+                }
+            }
+        }
+        plan.push((status, depot, local_path, scratch_file_path(&scratch_dir, depot)));
+    }
+    if !problems.is_empty() {
+        println!("{}", "Can't shelve the scratch changes through your workspace:".bright_red());
+        for problem in &problems {
+            println!("  {}", problem);
+        }
+        return Ok(());
+    }
+    
+    println!("Files to shelve (from scratch of CL {}):", source_cl);
+    for (status, depot) in &changes {
+        println!("  {} {}", status, depot);
+    }
+    println!("\nShelve these into a new CL? (y/n):");
+    
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    let answer = input.trim().to_lowercase();
+    if answer != "y" && answer != "yes" {
+        println!("Cancelled.");
+        return Ok(());
+    }
+    
+    println!();
+    let new_cl = match create_changelist_interactive(&[])? {
+        Some(cl) => cl,
+        None => {
+            println!("Cancelled.");
+            return Ok(());
+        }
+    };
+    println!("{}", format!("✓ Created CL {}", new_cl).bright_green());
+    
+    // Open each file in the new CL with the scratch content, remembering files we create for adds
+    println!("\nOpening files in CL {}...", new_cl);
+    let mut added_paths: Vec<&str> = Vec::new();
+    let mut failed = 0;
+    for (status, depot, local_path, scratch_path) in &plan {
+        let result = match *status {
+            "D" => perforce::open_file("delete", &new_cl, depot),
+            "A" => {
+                if let Some(parent) = std::path::Path::new(local_path).parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::copy(scratch_path, local_path)?;
+                added_paths.push(local_path);
+                perforce::open_file("add", &new_cl, local_path)
+            }
+            _ => perforce::open_file("edit", &new_cl, depot).and_then(|output| {
+                std::fs::copy(scratch_path, local_path)?;
+                Ok(output)
+            }),
+        };
+        
+        match result {
+            Ok(_) => println!("  {} {}", "✓".bright_green(), depot),
+            Err(e) => {
+                eprintln!("  {} {}: {}", "✗".bright_red(), depot, e);
+                failed += 1;
+            }
+        }
+    }
+    
+    let shelve_result = if failed == 0 {
+        perforce::shelve_changelist(&new_cl)
+    } else {
+        Err(anyhow::anyhow!("{} file(s) could not be opened", failed))
+    };
+    
+    // Put the workspace back the way it was: revert restores edits and deletes, adds are removed by hand
+    println!("\nRestoring your workspace...");
+    let revert_output = std::process::Command::new("p4")
+        .arg("revert")
+        .arg("-c")
+        .arg(&new_cl)
+        .arg("//...")
+        .output()?;
+    if !revert_output.status.success() {
+        eprintln!("Warning: Failed to revert CL {}: {}", new_cl, String::from_utf8_lossy(&revert_output.stderr));
+    }
+    for path in &added_paths {
+        std::fs::remove_file(path).ok();
+    }
+    
+    match shelve_result {
+        Ok(_) => {
+            config::mark_shelved(&new_cl)?;
+            println!("\n{}", format!("✓ Scratch changes shelved in CL {}", new_cl).bright_green());
+        }
+        Err(e) => {
+            // Don't leave an empty CL behind
+            eprintln!("\n{}", "Error shelving files:".bright_red());
+            eprintln!("{}", e);
+            match perforce::delete_changelist(&new_cl) {
+                Ok(()) => remove_tracked_cl(&new_cl)?,
+                Err(e) => eprintln!("Warning: Failed to delete CL {}: {}", new_cl, e),
+            }
+            return Err(anyhow::anyhow!("p4 shelve command failed"));
+        }
+    }
+    
+    Ok(())
+}
+
+/// Why a tracked CL no longer needs tracking, or None if it's still pending in our client.
+fn prune_reason(status: &perforce::ChangeStatus, current_client: &str) -> Option<String> {
+    match status {
+        perforce::ChangeStatus::Submitted { change, .. } => Some(format!("submitted as CL {}", change)),
+        perforce::ChangeStatus::Deleted => Some("deleted".to_string()),
+        perforce::ChangeStatus::Pending { client } if client != current_client => {
+            Some(format!("belongs to client {}", client))
+        }
+        perforce::ChangeStatus::Pending { .. } => None,
+    }
+}
+
+fn prune_tracked_cls() -> Result<()> {
+    let tracked_cls = read_tracked_cls()?;
+    let statuses = perforce::get_change_statuses(&tracked_cls)?;
+    let current_client = perforce::get_current_client()?;
+    
+    let mut pruned = 0;
+    for cl in &tracked_cls {
+        if let Some(reason) = statuses.get(cl).and_then(|s| prune_reason(s, &current_client)) {
+            remove_tracked_cl(cl)?;
+            println!("  {} CL {} ({})", "-".bright_red(), cl, reason);
+            pruned += 1;
+        }
+    }
+    
+    if pruned > 0 {
+        println!("{}", format!("✓ Stopped tracking {} CL(s)", pruned).bright_green());
+    } else {
+        println!("No submitted, deleted or foreign CLs to prune.");
+    }
+    println!();
+    
+    Ok(())
+}
+
+fn cmd_ls(prune: bool) -> Result<()> {
+    if prune {
+        prune_tracked_cls()?;
+    }
+    
     loop {
-        // Get tracked CLs from config
-        let tracked_cls = read_tracked_cls()?;
+        // Get tracked CLs (with notes and tags) from config
+        let tracked = config::get_tracked_cls()?;
+        let tracked_cls: Vec<String> = tracked.iter().map(|t| t.cl.clone()).collect();
+        let tracked_info: HashMap<String, config::TrackedCl> = tracked
+            .into_iter()
+            .map(|t| (t.cl.clone(), t))
+            .collect();
         
         // Get currently opened files
         let opened = perforce::get_opened_files()?;
@@ -1647,49 +2477,86 @@ fn cmd_ls() -> Result<()> {
             return Ok(());
         }
         
-        // Fetch descriptions for each CL
-        let mut cl_descriptions: HashMap<String, String> = HashMap::new();
-        for cl in &cls {
-            if let Ok(Some(desc)) = perforce::get_change_description(cl) {
-                let first_line = desc.lines().next().unwrap_or("").trim();
-                cl_descriptions.insert(cl.clone(), first_line.to_string());
-            }
-        }
-        
-        // Check if opened files differ from shelved files for each CL
-        let mut cl_has_diff: HashMap<String, bool> = HashMap::new();
+        // Look up which CLs were submitted or deleted, or aren't ours
+        let statuses = perforce::get_change_statuses(&cls).unwrap_or_default();
+        let current_client = perforce::get_current_client()?;
+        
+        // Resolve and out-of-date state of all opened files, in one call
+        let file_states = perforce::get_opened_file_states().unwrap_or_default();
+        
+        let mut rows: Vec<ClRow> = Vec::new();
         for cl in &cls {
             let file_count = cl_file_count.get(cl).copied().unwrap_or(0);
             
-            // Only check if CL has opened files
+            let description = match perforce::get_change_description(cl) {
+                Ok(Some(desc)) => desc.lines().next().unwrap_or("").trim().to_string(),
+                _ => String::new(),
+            };
+            
+            let status = match statuses.get(cl) {
+                Some(perforce::ChangeStatus::Submitted { change, .. }) if change == cl => {
+                    Some("[submitted]".bright_green().to_string())
+                }
+                Some(perforce::ChangeStatus::Submitted { change, .. }) => {
+                    Some(format!("[submitted as CL {}]", change).bright_green().to_string())
+                }
+                Some(perforce::ChangeStatus::Deleted) => Some("[deleted]".bright_red().to_string()),
+                Some(perforce::ChangeStatus::Pending { client }) if client != &current_client => {
+                    Some(format!("[client {}]", client).bright_yellow().to_string())
+                }
+                _ => None,
+            };
+            
+            // Compare opened files with shelved files (only if CL has opened files)
+            // The server knows about shelves made with plain p4 or on other machines; the config's
+            // record of our own shelves is only a fallback if it can't be asked
+            let info = tracked_info.get(cl);
+            let (shelved_files, last_shelved) = perforce::get_shelf(cl)
+                .unwrap_or_else(|_| (Vec::new(), info.and_then(|t| t.last_shelved.clone())));
+            let mut has_diff = false;
             if file_count > 0 {
-                // Get opened files for this CL
                 let opened_files: std::collections::HashSet<String> = opened
                     .iter()
                     .filter(|f| &f.changelist == cl)
                     .map(|f| f.depot_file.clone())
                     .collect();
-                
-                // Get shelved files for this CL
-                if let Ok(shelved_files) = perforce::get_shelved_files(cl) {
-                    let shelved_paths: std::collections::HashSet<String> = shelved_files
-                        .iter()
-                        .map(|f| f.depot_file.clone())
-                        .collect();
-                    
-                    // Check if the sets differ
-                    if opened_files != shelved_paths {
-                        cl_has_diff.insert(cl.clone(), true);
-                    }
-                }
+                let shelved_paths: std::collections::HashSet<String> = shelved_files
+                    .iter()
+                    .map(|f| f.depot_file.clone())
+                    .collect();
+                has_diff = opened_files != shelved_paths;
             }
+            
+            let cl_states: Vec<_> = file_states.iter().filter(|f| &f.changelist == cl).collect();
+            
+            rows.push(ClRow {
+                cl: cl.clone(),
+                description,
+                file_count,
+                shelved_count: shelved_files.len(),
+                has_diff,
+                last_shelved,
+                resolves: cl_states.iter().filter(|f| f.unresolved).count(),
+                out_of_date: cl_states.iter().filter(|f| f.is_out_of_date()).count(),
+                jobs: perforce::get_fixes(cl).map(|j| j.len()).unwrap_or(0),
+                review: info.and_then(|t| t.review.clone()),
+                status,
+                tags: info.map(|t| t.tags.clone()).unwrap_or_default(),
+                note: info.map(|t| t.note.clone()).unwrap_or_default(),
+            });
         }
         
+        let prunable = rows.iter().filter(|r| r.status.is_some()).count();
+        
         println!("Tracked changelists:");
+        if prunable > 0 {
+            println!("{}", format!("Tip: {} CL(s) were submitted, deleted or belong to another client. Run 'p ls --prune' to stop tracking them.",
+                prunable).bright_black());
+        }
         println!();
         
         // Use interactive selector with delete capability
-        match interactive_cl_select_with_delete(&cls, &cl_descriptions, &cl_file_count, &cl_has_diff)? {
+        match interactive_cl_select_with_delete(&rows)? {
             None => {
                 // User cancelled or quit
                 return Ok(());
@@ -1715,6 +2582,7 @@ fn interactive_file_select(
     cl_to_color: &HashMap<String, fn(&str) -> String>,
     cl_descriptions: &HashMap<String, String>,
     pre_select_all: bool,
+    unchanged_files: Option<&std::collections::HashSet<String>>, // depot paths; enables the 'u' key
 ) -> Result<Vec<perforce::OpenedFile>> {
     // Group files by changelist
     let mut cl_to_files: HashMap<String, Vec<usize>> = HashMap::new();
@@ -1777,7 +2645,8 @@ fn interactive_file_select(
             std::io::stdout().flush()?;
             
             // Display header
-            print!("Select files or CLs (↑/↓ to navigate, Tab to jump to next CL, Space to toggle, Enter to confirm, Esc/q to cancel):\r\n\r\n");
+            let unchanged_hint = if unchanged_files.is_some() { ", 'u' to select unchanged files" } else { "" };
+            print!("Select files or CLs (↑/↓ to navigate, Tab to jump to next CL, Space to toggle{}, Enter to confirm, Esc/q to cancel):\r\n\r\n", unchanged_hint);
             
             // Display items
             for (idx, item) in items.iter().enumerate() {
@@ -1786,8 +2655,11 @@ fn interactive_file_select(
                 match item {
                     SelectItem::ClHeader(cl) => {
                         let color = cl_to_color.get(cl).unwrap();
+                        // Groups that aren't changelists (e.g. in p status) are shown by name
                         let cl_label = if cl == "default" {
-                            "default (pending)".to_string()
+                            "CL default (pending)".to_string()
+                        } else if cl.parse::<i64>().is_ok() {
+                            format!("CL {}", cl)
                         } else {
                             cl.clone()
                         };
@@ -1807,18 +2679,14 @@ fn interactive_file_select(
                         
                         // Format with description if available
                         let line = if let Some(desc) = cl_descriptions.get(cl) {
-                            format!("{}  {} 📋 CL {} - {} — {} file(s)", 
+                            format!("{}  {} 📋 {} - {} — {} file(s)", 
                                 arrow, checkbox, cl_label, desc, file_indices.len())
                         } else {
-                            format!("{}  {} 📋 CL {} — {} file(s)", 
+                            format!("{}  {} 📋 {} — {} file(s)", 
                                 arrow, checkbox, cl_label, file_indices.len())
                         };
                         
-                        if idx == selected_idx {
-                            print!("{}\r\n", color(&line).bold().to_string());
-                        } else {
-                            print!("{}\r\n", color(&line).bold().to_string());
-                        }
+                        print!("{}\r\n", color(&line).bold());
                     }
                     SelectItem::File(file_idx) => {
                         let file = &files[*file_idx];
@@ -1826,11 +2694,12 @@ fn interactive_file_select(
                         
                         let checkbox = if selected_set.contains(file_idx) { "[✓]" } else { "[ ]" };
                         
-                        let line = format!("  {}  {}     {}", 
-                            arrow, checkbox, file.depot_file);
+                        let unchanged = unchanged_files.map(|u| u.contains(&file.depot_file)).unwrap_or(false);
+                        let line = format!("  {}  {}     {}{}", 
+                            arrow, checkbox, file.depot_file, if unchanged { " (unchanged)" } else { "" });
                         
                         if idx == selected_idx {
-                            print!("{}\r\n", color(&line).bold().to_string());
+                            print!("{}\r\n", color(&line).bold());
                         } else {
                             print!("{}\r\n", color(&line));
                         }
@@ -1884,22 +2753,21 @@ fn interactive_file_select(
                     }
                     KeyCode::Tab => {
                         // Jump to the next CL header
-                        let mut found_next = false;
-                        for i in (selected_idx + 1)..items.len() {
-                            if matches!(items[i], SelectItem::ClHeader(_)) {
-                                selected_idx = i;
-                                found_next = true;
-                                break;
-                            }
-                        }
+                        let next = items
+                            .iter()
+                            .enumerate()
+                            .skip(selected_idx + 1)
+                            .find(|(_, item)| matches!(item, SelectItem::ClHeader(_)))
+                            .map(|(i, _)| i);
                         // If no CL found after current position, wrap to first CL
-                        if !found_next {
-                            for i in 0..=selected_idx {
-                                if matches!(items[i], SelectItem::ClHeader(_)) {
-                                    selected_idx = i;
-                                    break;
-                                }
-                            }
+                        let next = next.or_else(|| {
+                            items
+                                .iter()
+                                .take(selected_idx + 1)
+                                .position(|item| matches!(item, SelectItem::ClHeader(_)))
+                        });
+                        if let Some(i) = next {
+                            selected_idx = i;
                         }
                     }
                     KeyCode::BackTab => {
@@ -1922,6 +2790,13 @@ fn interactive_file_select(
                             }
                         }
                     }
+                    KeyCode::Char('u') if unchanged_files.is_some() => {
+                        // Select exactly the files without content changes
+                        let unchanged = unchanged_files.unwrap();
+                        selected_set = (0..files.len())
+                            .filter(|&i| unchanged.contains(&files[i].depot_file))
+                            .collect();
+                    }
                     KeyCode::Char(' ') => {
                         match &items[selected_idx] {
                             SelectItem::ClHeader(cl) => {
@@ -1989,14 +2864,85 @@ fn interactive_file_select(
     result
 }
 
-fn interactive_cl_select_with_delete(
-    items: &[String],
-    descriptions: &HashMap<String, String>,
-    file_counts: &HashMap<String, usize>,
-    has_diff: &HashMap<String, bool>,
-) -> Result<Option<String>> {
+// One row of the 'p ls' dashboard
+struct ClRow {
+    cl: String,
+    description: String, // first line only
+    file_count: usize,
+    shelved_count: usize,
+    has_diff: bool, // opened files differ from shelved files
+    last_shelved: Option<String>,
+    resolves: usize,    // opened files needing resolve
+    out_of_date: usize, // opened files behind the head revision
+    jobs: usize,
+    review: Option<String>,
+    status: Option<String>, // colored submitted/deleted/foreign marker
+    tags: Vec<String>,
+    note: String,
+}
+
+const LS_SORT_COLUMNS: [&str; 7] = ["CL", "Files", "Shelved", "Last shelve", "Resolve", "Stale", "Review"];
+
+fn sort_cl_rows(rows: &[ClRow], view: &mut [usize], column: usize, reverse: bool) {
+    view.sort_by(|&a, &b| {
+        let (a, b) = (&rows[a], &rows[b]);
+        let by_cl = match (a.cl.parse::<i64>(), b.cl.parse::<i64>()) {
+            (Ok(x), Ok(y)) => x.cmp(&y),
+            _ => a.cl.cmp(&b.cl),
+        };
+        let ordering = match column {
+            1 => a.file_count.cmp(&b.file_count),
+            2 => a.shelved_count.cmp(&b.shelved_count),
+            // Dates are "YYYY/MM/DD hh:mm:ss", so they sort as strings; never-shelved first
+            3 => a.last_shelved.cmp(&b.last_shelved),
+            4 => a.resolves.cmp(&b.resolves),
+            5 => a.out_of_date.cmp(&b.out_of_date),
+            6 => a.review.cmp(&b.review),
+            _ => std::cmp::Ordering::Equal,
+        };
+        let ordering = ordering.then(by_cl);
+        if reverse { ordering.reverse() } else { ordering }
+    });
+}
+
+fn cl_row_matches(row: &ClRow, filter: &str) -> bool {
+    let filter = filter.to_lowercase();
+    row.cl.contains(&filter)
+        || row.description.to_lowercase().contains(&filter)
+        || row.note.to_lowercase().contains(&filter)
+        || row.tags.iter().any(|t| t.to_lowercase().contains(&filter))
+        || row.review.as_deref().unwrap_or("").to_lowercase().contains(&filter)
+}
+
+/// Cut a string down to `width` columns, marking the cut with '…'.
+fn truncate_to_width(s: &str, width: usize) -> String {
+    if unicode_width::UnicodeWidthStr::width(s) <= width {
+        return s.to_string();
+    }
+    let mut out = String::new();
+    let mut used = 0;
+    for c in s.chars() {
+        let w = unicode_width::UnicodeWidthChar::width(c).unwrap_or(0);
+        if used + w + 1 > width {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+    out.push('…');
+    out
+}
+
+fn interactive_cl_select_with_delete(rows: &[ClRow]) -> Result<Option<String>> {
     let mut selected_idx = 0usize;
     
+    // Rows currently shown, as indices into `rows`, after filtering and sorting
+    let mut sort_column = 0usize;
+    let mut sort_reverse = false;
+    let mut filter = String::new();
+    let mut filtering = false;
+    let mut view: Vec<usize> = (0..rows.len()).collect();
+    
     // Capture the starting position (before entering raw mode)
     let start_pos = cursor::position()?;
     
@@ -2008,7 +2954,18 @@ fn interactive_cl_select_with_delete(
         let mut render_pos = start_pos;
         let mut first_render = true;
         
+        let cl_width = rows.iter().map(|r| r.cl.len()).max().unwrap_or(2).max(2);
+        
         loop {
+            // Rebuild the view from the current filter and sort order
+            view = (0..rows.len())
+                .filter(|&i| filter.is_empty() || cl_row_matches(&rows[i], &filter))
+                .collect();
+            sort_cl_rows(rows, &mut view, sort_column, sort_reverse);
+            if selected_idx >= view.len() {
+                selected_idx = view.len().saturating_sub(1);
+            }
+            
             // Move cursor to render position and clear from here down
             execute!(
                 std::io::stdout(),
@@ -2017,51 +2974,92 @@ fn interactive_cl_select_with_delete(
             )?;
             std::io::stdout().flush()?;
             
+            let (term_width, _) = terminal::size()?;
+            
             // Display header
-            print!("Tracked CLs (↑/↓ to navigate, 'd' to delete, 'u' to unshelve, 's' to show file diff, Esc/q to cancel):\r\n\r\n");
+            print!("Tracked CLs (↑/↓ to navigate, Enter for details, 'e' to edit description, 'd' to delete, 'u' to unshelve, 's' to show file diff, 'o'/'O' to sort, '/' to filter, Esc/q to cancel):\r\n\r\n");
+            
+            let columns = format!(
+                "{:>cl_width$}  {:>5} {:>7}  {:<16}  {:>7} {:>5} {:>4}  {:<10}  {}",
+                "CL", "Files", "Shelved", "Last shelve", "Resolve", "Stale", "Jobs", "Review", "Description",
+                cl_width = cl_width,
+            );
+            print!("     {}\r\n", truncate_to_width(&columns, (term_width as usize).saturating_sub(6)).bold());
             
             // Display items
-            for (idx, item) in items.iter().enumerate() {
-                let file_count = file_counts.get(item).copied().unwrap_or(0);
-                let desc = descriptions.get(item).map(|s| s.as_str()).unwrap_or("");
-                let has_file_diff = has_diff.get(item).copied().unwrap_or(false);
+            for (idx, &row_idx) in view.iter().enumerate() {
+                let row = &rows[row_idx];
                 
-                let display = if file_count == 0 {
-                    // Empty CL - show in gray
-                    if desc.is_empty() {
-                        format!("CL {} [empty]", item).bright_black().to_string()
-                    } else {
-                        format!("CL {} - {} [empty]", item, desc).bright_black().to_string()
-                    }
-                } else {
-                    // CL with files
-                    let base_text = if desc.is_empty() {
-                        format!("CL {} — {} file(s)", item, file_count)
-                    } else {
-                        format!("CL {} - {} — {} file(s)", item, desc, file_count)
-                    };
-                    
-                    // If files differ from shelved, add indicator with only the indicator in yellow
-                    if has_file_diff {
-                        format!("{} {}", base_text, "[files differ from shelved]".bright_yellow())
-                    } else {
-                        base_text
-                    }
-                };
+                let last_shelved = row.last_shelved.as_deref().map(|d| d.chars().take(16).collect::<String>());
+                let fixed = format!(
+                    "{:>cl_width$}  {:>5} {:>7}  {:<16}  {:>7} {:>5} {:>4}  {:<10}  ",
+                    row.cl,
+                    row.file_count,
+                    row.shelved_count,
+                    last_shelved.as_deref().unwrap_or("-"),
+                    row.resolves,
+                    row.out_of_date,
+                    if row.jobs > 0 { row.jobs.to_string() } else { "-".to_string() },
+                    truncate_to_width(row.review.as_deref().unwrap_or("-"), 10),
+                    cl_width = cl_width,
+                );
+                
+                // Markers after the description, e.g. " #perf (waiting on infra) [files differ from shelved]"
+                let mut markers = String::new();
+                for tag in &row.tags {
+                    markers.push_str(&format!(" {}", format!("#{}", tag).bright_magenta()));
+                }
+                if !row.note.is_empty() {
+                    markers.push_str(&format!(" ({})", row.note));
+                }
+                if row.file_count == 0 {
+                    markers.push_str(" [empty]");
+                }
+                if row.has_diff {
+                    markers.push_str(&format!(" {}", "[files differ from shelved]".bright_yellow()));
+                }
+                if let Some(ref status) = row.status {
+                    markers.push_str(&format!(" {}", status));
+                }
+                
+                // Shorten the description so the row doesn't wrap
+                let desc_width = (term_width as usize)
+                    .saturating_sub(5 + visual_width(&fixed) + visual_width(&markers))
+                    .max(10);
+                let display = format!("{}{}{}", fixed, truncate_to_width(&row.description, desc_width), markers);
                 
                 if idx == selected_idx {
                     print!("  {}  {}\r\n", "→".bright_green(), display.bright_green().bold());
+                } else if row.file_count == 0 {
+                    // Empty CL - show in gray
+                    print!("     {}\r\n", display.bright_black());
+                } else if row.resolves > 0 {
+                    print!("     {}\r\n", display.bright_red());
                 } else {
                     print!("     {}\r\n", display);
                 }
             }
             
+            if view.is_empty() {
+                print!("     {}\r\n", "(no changelists match the filter)".bright_black());
+            }
+            
+            // Footer: sort order and filter
+            let arrow = if sort_reverse { "▼" } else { "▲" };
+            let mut footer = format!("Sort: {} {}", LS_SORT_COLUMNS[sort_column], arrow);
+            if filtering {
+                footer.push_str(&format!(" | Filter: {}_ (Enter to apply, Esc to clear)", filter));
+            } else if !filter.is_empty() {
+                footer.push_str(&format!(" | Filter: '{}' ({}/{} CLs)", filter, view.len(), rows.len()));
+            }
+            print!("\r\n{}\r\n", footer.bright_black());
+            
             std::io::stdout().flush()?;
             
             // After first render, adjust render_pos if scrolling occurred
             if first_render {
                 let end_pos = cursor::position()?;
-                let lines_rendered = 2 + items.len(); // header + blank + items
+                let lines_rendered = 3 + view.len().max(1) + 2; // header + blank + columns + items + blank + footer
                 
                 // Calculate where we should have ended up (cursor is after last line)
                 let expected_end_row = render_pos.1 + lines_rendered as u16;
@@ -2080,23 +3078,92 @@ fn interactive_cl_select_with_delete(
             
             // Read key event
             if let Event::Key(KeyEvent { code, .. }) = event::read()? {
+                // While typing a filter, keys edit the filter text
+                if filtering {
+                    match code {
+                        KeyCode::Enter => filtering = false,
+                        KeyCode::Esc => {
+                            filtering = false;
+                            filter.clear();
+                        }
+                        KeyCode::Backspace => {
+                            filter.pop();
+                        }
+                        KeyCode::Char(c) => filter.push(c),
+                        _ => {}
+                    }
+                    selected_idx = 0;
+                    continue;
+                }
+                
+                // Actions need a selected row
+                let is_action = matches!(code, KeyCode::Enter | KeyCode::Char('d' | 'D' | 'e' | 'E' | 'u' | 'U' | 's' | 'S'));
+                if view.is_empty() && (is_action || matches!(code, KeyCode::Up | KeyCode::Down)) {
+                    continue;
+                }
+                
                 match code {
                     KeyCode::Up => {
                         if selected_idx > 0 {
                             selected_idx -= 1;
                         } else {
-                            selected_idx = items.len() - 1;
+                            selected_idx = view.len() - 1;
                         }
                     }
                     KeyCode::Down => {
-                        if selected_idx < items.len() - 1 {
+                        if selected_idx < view.len() - 1 {
                             selected_idx += 1;
                         } else {
                             selected_idx = 0;
                         }
                     }
+                    KeyCode::Char('o') => {
+                        sort_column = (sort_column + 1) % LS_SORT_COLUMNS.len();
+                    }
+                    KeyCode::Char('O') => {
+                        sort_reverse = !sort_reverse;
+                    }
+                    KeyCode::Char('/') => {
+                        filtering = true;
+                    }
+                    KeyCode::Enter => {
+                        let cl = rows[view[selected_idx]].cl.clone();
+                        if cl_detail_pane(&cl)? {
+                            // The CL was edited, shelved or submitted: clear the menu and refresh
+                            terminal::disable_raw_mode()?;
+                            execute!(
+                                std::io::stdout(),
+                                cursor::MoveTo(render_pos.0, render_pos.1),
+                                terminal::Clear(ClearType::FromCursorDown)
+                            )?;
+                            return Ok(Some(cl));
+                        }
+                    }
+                    KeyCode::Char('e') | KeyCode::Char('E') => {
+                        let cl = rows[view[selected_idx]].cl.clone();
+                        terminal::disable_raw_mode()?;
+                        
+                        // Clear the menu
+                        execute!(
+                            std::io::stdout(),
+                            cursor::MoveTo(render_pos.0, render_pos.1),
+                            terminal::Clear(ClearType::FromCursorDown)
+                        )?;
+                        
+                        match edit_description(&cl, false) {
+                            // Refresh the list to show the new description
+                            Ok(true) => return Ok(Some(cl)),
+                            Ok(false) => {}
+                            Err(e) => println!("{} {}", "✗".bright_red(), e),
+                        }
+                        
+                        println!("\nPress any key to continue...");
+                        terminal::enable_raw_mode()?;
+                        event::read()?;
+                    }
                     KeyCode::Char('d') | KeyCode::Char('D') => {
-                        let cl = &items[selected_idx];
+                        let row = &rows[view[selected_idx]];
+                        let cl = &row.cl;
                         terminal::disable_raw_mode()?;
                         
                         // Clear the menu
@@ -2108,11 +3175,11 @@ fn interactive_cl_select_with_delete(
                         
                         // Ask for confirmation
                         println!("{}", format!("Delete CL {}?", cl).bright_yellow().bold());
-                        if let Some(desc) = descriptions.get(cl) {
-                            println!("Description: {}", desc.bright_cyan());
+                        if !row.description.is_empty() {
+                            println!("Description: {}", row.description.bright_cyan());
                         }
                         
-                        let file_count = file_counts.get(cl).copied().unwrap_or(0);
+                        let file_count = row.file_count;
                         if file_count > 0 {
                             println!("{}", format!("This will revert {} opened file(s).", file_count).bright_red());
                         }
@@ -2158,7 +3225,8 @@ fn interactive_cl_select_with_delete(
                         }
                     }
                     KeyCode::Char('u') | KeyCode::Char('U') => {
-                        let cl = items[selected_idx].clone();
+                        let row = &rows[view[selected_idx]];
+                        let cl = row.cl.clone();
                         terminal::disable_raw_mode()?;
                         
                         // Clear the menu
@@ -2169,8 +3237,8 @@ fn interactive_cl_select_with_delete(
                         )?;
                         
                         println!("Selected: CL {}", cl.bright_cyan().bold());
-                        if let Some(desc) = descriptions.get(&cl) {
-                            println!("Description: {}", desc.bright_cyan());
+                        if !row.description.is_empty() {
+                            println!("Description: {}", row.description.bright_cyan());
                         }
                         println!();
                         
@@ -2236,9 +3304,17 @@ fn interactive_cl_select_with_delete(
                                 
                                 if let Some(target) = interactive_select_with_desc(&empty_cls, &cl_descriptions)? {
                                     if target == "[Create new CL]" {
-                                        let new_cl = perforce::create_changelist()?;
-                                        println!("Created new CL: {}", new_cl.bright_green());
-                                        new_cl
+                                        match create_changelist_interactive(&[])? {
+                                            Some(new_cl) => {
+                                                println!("Created new CL: {}", new_cl.bright_green());
+                                                new_cl
+                                            }
+                                            None => {
+                                                println!("Cancelled.");
+                                                terminal::enable_raw_mode()?;
+                                                continue;
+                                            }
+                                        }
                                     } else {
                                         target
                                     }
@@ -2265,7 +3341,7 @@ fn interactive_cl_select_with_delete(
                         let cl_descriptions_empty: HashMap<String, String> = HashMap::new();
                         
                         // Interactive file selector - all files pre-selected
-                        let selected_files = interactive_file_select(&shelved_files, &cl_to_color, &cl_descriptions_empty, true)?;
+                        let selected_files = interactive_file_select(&shelved_files, &cl_to_color, &cl_descriptions_empty, true, None)?;
                         
                         if selected_files.is_empty() {
                             println!("No files selected.");
@@ -2353,7 +3429,8 @@ fn interactive_cl_select_with_delete(
                         event::read()?;
                     }
                     KeyCode::Char('s') | KeyCode::Char('S') => {
-                        let cl = items[selected_idx].clone();
+                        let row = &rows[view[selected_idx]];
+                        let cl = row.cl.clone();
                         terminal::disable_raw_mode()?;
                         
                         // Clear the menu
@@ -2364,12 +3441,12 @@ fn interactive_cl_select_with_delete(
                         )?;
                         
                         println!("File diff for CL {}", cl.bright_cyan().bold());
-                        if let Some(desc) = descriptions.get(&cl) {
-                            println!("Description: {}", desc.bright_cyan());
+                        if !row.description.is_empty() {
+                            println!("Description: {}", row.description.bright_cyan());
                         }
                         println!();
                         
-                        let file_count = file_counts.get(&cl).copied().unwrap_or(0);
+                        let file_count = row.file_count;
                         
                         if file_count == 0 {
                             println!("No opened files in CL {}", cl);
@@ -2389,7 +3466,7 @@ fn interactive_cl_select_with_delete(
                         let opened = perforce::get_opened_files()?;
                         let opened_files: Vec<_> = opened
                             .iter()
-                            .filter(|f| &f.changelist == &cl)
+                            .filter(|f| f.changelist == cl)
                             .map(|f| f.depot_file.clone())
                             .collect();
                         
@@ -2443,6 +3520,28 @@ fn interactive_cl_select_with_delete(
                             }
                         }
                         
+                        // Files that need attention before submitting
+                        if row.resolves > 0 || row.out_of_date > 0 {
+                            let states = perforce::get_opened_file_states().unwrap_or_default();
+                            let cl_states: Vec<_> = states.iter().filter(|f| f.changelist == cl).collect();
+                            
+                            if cl_states.iter().any(|f| f.unresolved) {
+                                println!("{}", "Files needing resolve:".bright_red().bold());
+                                for file in cl_states.iter().filter(|f| f.unresolved) {
+                                    println!("  {} {}", "!".bright_red(), file.depot_file);
+                                }
+                                println!();
+                            }
+                            if cl_states.iter().any(|f| f.is_out_of_date()) {
+                                println!("{}", "Files out of date with head:".bright_yellow().bold());
+                                for file in cl_states.iter().filter(|f| f.is_out_of_date()) {
+                                    println!("  {} {}#{} (head #{})", "↓".bright_yellow(), file.depot_file,
+                                        file.have_rev.as_deref().unwrap_or("?"), file.head_rev.as_deref().unwrap_or("?"));
+                                }
+                                println!();
+                            }
+                        }
+                        
                         println!("\nPress 'q' to return...");
                         terminal::enable_raw_mode()?;
                         loop {
@@ -2475,8 +3574,286 @@ fn interactive_cl_select_with_delete(
     result
 }
 
+/// Truncate a string that may contain ANSI color codes to `width` visible columns.
+fn truncate_colored_to_width(s: &str, width: usize) -> String {
+    if visual_width(s) <= width {
+        return s.to_string();
+    }
+    let mut out = String::new();
+    let mut used = 0;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Copy escape sequences through without counting them
+            out.push(c);
+            for c in chars.by_ref() {
+                out.push(c);
+                if c == 'm' {
+                    break;
+                }
+            }
+            continue;
+        }
+        let w = unicode_width::UnicodeWidthChar::width(c).unwrap_or(0);
+        if used + w + 1 > width {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+    out.push_str("\x1b[0m…");
+    out
+}
+
+/// Color a line of unified diff output.
+fn colorize_diff_line(line: &str) -> String {
+    if line.starts_with("====") {
+        line.bright_blue().bold().to_string()
+    } else if line.starts_with("+++") || line.starts_with("---") {
+        line.bold().to_string()
+    } else if line.starts_with('+') {
+        line.bright_green().to_string()
+    } else if line.starts_with('-') {
+        line.bright_red().to_string()
+    } else if line.starts_with("@@") {
+        line.bright_cyan().to_string()
+    } else {
+        line.to_string()
+    }
+}
+
+/// Color a line of unified diff output, highlighting the code of added, removed and context lines.
+fn colorize_code_diff_line(line: &str, language: Option<&highlight::Language>) -> String {
+    let Some(lang) = language else {
+        return colorize_diff_line(line);
+    };
+    if line.starts_with("+++") || line.starts_with("---") {
+        return colorize_diff_line(line);
+    }
+    match line.chars().next() {
+        Some('+') => format!("{}{}", "+".bright_green().bold(), highlight::highlight(&line[1..], lang)),
+        Some('-') => format!("{}{}", "-".bright_red().bold(), highlight::highlight(&line[1..], lang)),
+        Some(' ') => format!(" {}", highlight::highlight(&line[1..], lang)),
+        _ => colorize_diff_line(line),
+    }
+}
+
+/// Color the unified diff of several files, taking each file's language from its header.
+fn colorize_diff(diff: &str) -> Vec<String> {
+    let mut language = None;
+    diff.lines()
+        .map(|line| {
+            // "==== //depot/a.c#3 - /ws/a.c ====" or "+++ /ws/a.c\t<date>"
+            if let Some(header) = line.strip_prefix("==== ").or_else(|| line.strip_prefix("+++ ")) {
+                language = highlight::language_for(header.split(['\t', ' ']).next().unwrap_or(""));
+            }
+            colorize_code_diff_line(line, language)
+        })
+        .collect()
+}
+
+/// Full-screen scrollable viewer for pre-colored lines. Expects raw mode to be enabled.
+/// Returns None when the user quits, or Some(key) when one of `action_keys` is pressed.
+fn pager_viewer(title: &str, lines: &[String], action_keys: &[(char, &str)]) -> Result<Option<char>> {
+    let mut stdout = std::io::stdout();
+    let mut top_line = 0usize;
+    
+    loop {
+        let (term_width, term_height) = terminal::size()?;
+        let visible_lines = (term_height as usize).saturating_sub(2); // Title and status bar
+        top_line = top_line.min(lines.len().saturating_sub(visible_lines));
+        let end_line = (top_line + visible_lines).min(lines.len());
+        
+        execute!(stdout, cursor::MoveTo(0, 0), terminal::Clear(ClearType::All))?;
+        write!(stdout, "{}\r\n", truncate_to_width(title, term_width as usize).bold())?;
+        for line in &lines[top_line..end_line] {
+            write!(stdout, "{}\r\n", truncate_colored_to_width(line, term_width as usize))?;
+        }
+        
+        // Status bar
+        let keys = action_keys.iter().map(|(key, label)| format!("{}:{}", key, label)).join(" ");
+        let status = format!(
+            "Lines {}-{}/{} | q:back ↑↓:scroll PgUp/PgDn:page {}",
+            (top_line + 1).min(lines.len()),
+            end_line,
+            lines.len(),
+            keys
+        );
+        draw_status_bar(term_height.saturating_sub(1), &status)?;
+        stdout.flush()?;
+        
+        if let Event::Key(KeyEvent { code, .. }) = event::read()? {
+            match code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(None),
+                KeyCode::PageDown | KeyCode::Char(' ') => top_line += visible_lines,
+                KeyCode::PageUp => top_line = top_line.saturating_sub(visible_lines),
+                KeyCode::Down | KeyCode::Char('j') => top_line += 1,
+                KeyCode::Up | KeyCode::Char('k') => top_line = top_line.saturating_sub(1),
+                KeyCode::Home | KeyCode::Char('g') => top_line = 0,
+                KeyCode::End | KeyCode::Char('G') => top_line = lines.len(),
+                KeyCode::Char(c) if action_keys.iter().any(|(key, _)| *key == c) => return Ok(Some(c)),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Build the lines of the CL detail pane: description, jobs, opened and shelved files with diff stats.
+fn build_cl_detail_lines(cl: &str) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    
+    let description = perforce::get_change_description(cl)?.unwrap_or_default();
+    lines.push("Description:".bright_yellow().bold().to_string());
+    if description.is_empty() {
+        lines.push(format!("  {}", "(none)".bright_black()));
+    }
+    for line in description.lines() {
+        lines.push(format!("  {}", line.bright_cyan()));
+    }
+    lines.push(String::new());
+    
+    let jobs = perforce::get_fixes(cl).unwrap_or_default();
+    if !jobs.is_empty() {
+        lines.push("Jobs:".bright_yellow().bold().to_string());
+        for job in &jobs {
+            lines.push(format!("  {}", job));
+        }
+        lines.push(String::new());
+    }
+    
+    let opened: Vec<_> = perforce::get_opened_files()?
+        .into_iter()
+        .filter(|f| f.changelist == cl)
+        .collect();
+    let depot_files: Vec<String> = opened.iter().map(|f| f.depot_file.clone()).collect();
+    let stats = perforce::get_diff_stats(&depot_files).unwrap_or_default();
+    
+    lines.push(format!("{} ({})", "Opened files:".bright_yellow().bold(), opened.len()));
+    if opened.is_empty() {
+        lines.push(format!("  {}", "(none)".bright_black()));
+    }
+    for f in &opened {
+        let stat = match stats.get(&f.depot_file) {
+            Some((added, deleted)) => format!("{} {}", format!("+{}", added).bright_green(), format!("-{}", deleted).bright_red()),
+            None if f.action.contains("add") || f.action == "branch" => "new file".bright_green().to_string(),
+            None if f.action.contains("delete") => "removed".bright_red().to_string(),
+            None => String::new(),
+        };
+        lines.push(format!("  {}  {}", render_opened_line(f), stat));
+    }
+    lines.push(String::new());
+    
+    let shelved = perforce::get_shelved_files(cl).unwrap_or_default();
+    lines.push(format!("{} ({})", "Shelved files:".bright_yellow().bold(), shelved.len()));
+    if shelved.is_empty() {
+        lines.push(format!("  {}", "(none)".bright_black()));
+    }
+    for f in &shelved {
+        lines.push(format!("  {}", render_opened_line(f)));
+    }
+    
+    Ok(lines)
+}
+
+/// Wait for a key press after an action printed its output in the detail pane.
+fn wait_for_key() -> Result<()> {
+    println!("\nPress any key to continue...");
+    terminal::enable_raw_mode()?;
+    event::read()?;
+    Ok(())
+}
+
+/// Show the full details of a CL on the alternate screen, with keys to act on it.
+/// Expects raw mode to be enabled. Returns true if the CL was changed (so the caller should refresh).
+fn cl_detail_pane(cl: &str) -> Result<bool> {
+    let mut stdout = std::io::stdout();
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+    
+    let result = (|| -> Result<bool> {
+        let mut changed = false;
+        
+        loop {
+            let lines = build_cl_detail_lines(cl)?;
+            let title = format!("CL {}", cl);
+            let action = pager_viewer(&title, &lines, &[('d', "diff"), ('e', "edit description"), ('h', "shelve"), ('S', "submit")])?;
+            
+            match action {
+                None => return Ok(changed),
+                Some('d') => {
+                    let files: Vec<String> = perforce::get_opened_files()?
+                        .into_iter()
+                        .filter(|f| f.changelist == cl)
+                        .map(|f| f.depot_file)
+                        .collect();
+                    let diff = perforce::get_diff(&files)?;
+                    let mut diff_lines = colorize_diff(&diff);
+                    if diff_lines.is_empty() {
+                        diff_lines.push("No content changes in opened files.".bright_black().to_string());
+                    }
+                    pager_viewer(&format!("Diff of CL {}", cl), &diff_lines, &[])?;
+                }
+                Some('e') => {
+                    terminal::disable_raw_mode()?;
+                    execute!(stdout, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0), cursor::Show)?;
+                    match edit_description(cl, false) {
+                        Ok(edited) => changed |= edited,
+                        Err(e) => println!("{} {}", "✗".bright_red(), e),
+                    }
+                    execute!(stdout, cursor::Hide)?;
+                    wait_for_key()?;
+                }
+                Some('h') => {
+                    terminal::disable_raw_mode()?;
+                    execute!(stdout, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+                    println!("Shelving CL {}...", cl);
+                    match perforce::shelve_changelist(cl) {
+                        Ok(output) => {
+                            config::mark_shelved(cl)?;
+                            println!("\n{}", "✓ Successfully shelved files!".bright_green());
+                            print!("{}", output);
+                            changed = true;
+                        }
+                        Err(e) => {
+                            println!("\n{}", "✗ Error shelving files:".bright_red());
+                            println!("{}", e);
+                        }
+                    }
+                    wait_for_key()?;
+                }
+                Some('S') => {
+                    terminal::disable_raw_mode()?;
+                    execute!(stdout, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+                    let submitted = submit_with_checks(cl).unwrap_or_else(|e| {
+                        println!("\n{} {}", "✗".bright_red(), e);
+                        false
+                    });
+                    wait_for_key()?;
+                    if submitted {
+                        return Ok(true);
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+    })();
+    
+    execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+    result
+}
+
 fn interactive_select_with_desc(items: &[String], descriptions: &HashMap<String, String>) -> Result<Option<String>> {
-    let mut selected_idx = 0usize;
+    Ok(interactive_select_with_actions(items, descriptions, &[], 0)?.map(|(item, _)| item))
+}
+
+/// Like `interactive_select_with_desc`, with extra keys that act on the highlighted item. Starts on
+/// item `initial_idx`. Returns the item and the action key pressed, or None for Enter.
+fn interactive_select_with_actions(
+    items: &[String],
+    descriptions: &HashMap<String, String>,
+    action_keys: &[(char, &str)],
+    initial_idx: usize,
+) -> Result<Option<(String, Option<char>)>> {
+    let mut selected_idx = initial_idx.min(items.len().saturating_sub(1));
     
     // Capture the starting position (before entering raw mode)
     let start_pos = cursor::position()?;
@@ -2484,7 +3861,7 @@ fn interactive_select_with_desc(items: &[String], descriptions: &HashMap<String,
     // Enable raw mode
     terminal::enable_raw_mode()?;
     
-    let result = (|| -> Result<Option<String>> {
+    let result = (|| -> Result<Option<(String, Option<char>)>> {
         // Track the actual rendering position (may differ from start_pos after first render)
         let mut render_pos = start_pos;
         let mut first_render = true;
@@ -2499,7 +3876,8 @@ fn interactive_select_with_desc(items: &[String], descriptions: &HashMap<String,
             std::io::stdout().flush()?;
             
             // Display header
-            print!("Select a changelist (↑/↓ to navigate, Enter to edit, Esc/q to cancel):\r\n\r\n");
+            let keys: String = action_keys.iter().map(|(key, label)| format!(", {} to {}", key, label)).collect();
+            print!("Select a changelist (↑/↓ to navigate, Enter to edit{}, Esc/q to cancel):\r\n\r\n", keys);
             
             // Display items
             for (idx, item) in items.iter().enumerate() {
@@ -2578,7 +3956,16 @@ fn interactive_select_with_desc(items: &[String], descriptions: &HashMap<String,
                         } else {
                             format!("CL {}", result)
                         });
-                        return Ok(Some(result));
+                        return Ok(Some((result, None)));
+                    }
+                    KeyCode::Char(c) if action_keys.iter().any(|(key, _)| *key == c) => {
+                        terminal::disable_raw_mode()?;
+                        execute!(
+                            std::io::stdout(),
+                            cursor::MoveTo(render_pos.0, render_pos.1),
+                            terminal::Clear(ClearType::FromCursorDown)
+                        )?;
+                        return Ok(Some((items[selected_idx].clone(), Some(c))));
                     }
                     KeyCode::Esc | KeyCode::Char('q') => {
                         terminal::disable_raw_mode()?;
@@ -2677,97 +4064,1875 @@ where
 // Config file management for tracking CLs
 // ============================================================================
 
-fn get_config_path() -> Result<std::path::PathBuf> {
-    let home = std::env::var("HOME")?;
-    Ok(std::path::PathBuf::from(home).join(".pconfig"))
+fn read_tracked_cls() -> Result<Vec<String>> {
+    Ok(config::get_tracked_cls()?
+        .into_iter()
+        .map(|t| t.cl)
+        .collect())
 }
 
-fn read_tracked_cls() -> Result<Vec<String>> {
-    let config_path = get_config_path()?;
-    if !config_path.exists() {
-        return Ok(Vec::new());
+fn add_tracked_cl(cl: &str) -> Result<()> {
+    config::track_cl(cl)
+}
+
+fn remove_tracked_cl(cl: &str) -> Result<()> {
+    config::untrack_cl(cl)
+}
+
+fn cmd_track(cl: &str, note: Option<&str>, tags: &[String], untags: &[String], review: Option<&str>) -> Result<()> {
+    if cl.parse::<i64>().is_err() {
+        println!("Error: Invalid CL number '{}'", cl);
+        return Ok(());
+    }
+    
+    if perforce::get_change_description(cl)?.is_none() {
+        println!("Error: CL {} does not exist", cl);
+        return Ok(());
+    }
+    
+    config::update_tracked_cl(cl, |t| {
+        if let Some(note) = note {
+            t.note = note.to_string();
+        }
+        for tag in tags {
+            if !t.tags.contains(tag) {
+                t.tags.push(tag.clone());
+            }
+        }
+        t.tags.retain(|tag| !untags.contains(tag));
+        if let Some(review) = review {
+            t.review = if review.is_empty() || review == "none" {
+                None
+            } else {
+                Some(review.to_string())
+            };
+        }
+    })?;
+    
+    let tracked = config::get_tracked_cls()?;
+    if let Some(t) = tracked.iter().find(|t| t.cl == cl) {
+        println!("{}", format!("✓ Tracking CL {}", cl).bright_green());
+        println!("  Tracked since: {}", t.tracked_at);
+        if !t.note.is_empty() {
+            println!("  Note: {}", t.note.bright_cyan());
+        }
+        if !t.tags.is_empty() {
+            println!("  Tags: {}", t.tags.join(", ").bright_magenta());
+        }
+        if let Some(ref shelved) = t.last_shelved {
+            println!("  Last shelved: {}", shelved);
+        }
+        if let Some(ref review) = t.review {
+            println!("  Review: {}", review.bright_cyan());
+        }
+    }
+    
+    Ok(())
+}
+
+/// Print the ways a description breaks the team rules. Returns true if it follows them.
+fn check_description(desc: &str, rules: &config::DescriptionRules) -> bool {
+    let problems = description::validate(desc, rules);
+    if problems.is_empty() {
+        return true;
+    }
+    println!("{}", "✗ The description doesn't follow the team rules:".bright_red());
+    for problem in &problems {
+        println!("  - {}", problem);
+    }
+    false
+}
+
+/// Open `initial` in the user's editor ($P4EDITOR, $VISUAL, $EDITOR, falling back to vi) and return the result.
+fn edit_text_in_editor(initial: &str, name: &str) -> Result<String> {
+    let editor = ["P4EDITOR", "VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    
+    let path = std::env::temp_dir().join(format!("p-{}-{}.txt", name, std::process::id()));
+    std::fs::write(&path, initial)?;
+    
+    // Run through the shell so editors configured with arguments (e.g. "code --wait") work
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status();
+    
+    let content = std::fs::read_to_string(&path);
+    std::fs::remove_file(&path).ok();
+    
+    if !status?.success() {
+        anyhow::bail!("Editor '{}' exited with an error", editor);
+    }
+    Ok(content?)
+}
+
+/// Read a multi-line description from the terminal, ending with a line containing only ".".
+fn prompt_description_inline() -> Result<String> {
+    println!("Enter the description (finish with a line containing only '.'):");
+    let mut lines = Vec::new();
+    loop {
+        let mut input = String::new();
+        if std::io::stdin().read_line(&mut input)? == 0 {
+            break;
+        }
+        let line = input.trim_end();
+        if line == "." {
+            break;
+        }
+        lines.push(line.to_string());
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Trailing whitespace isn't significant to p4: strip it so comparisons and validation see what p4 stores.
+fn normalize_description(text: &str) -> String {
+    text.lines().map(|l| l.trim_end()).join("\n").trim_end().to_string()
+}
+
+/// Validate the description of a new CL, letting the user fix it in $EDITOR.
+/// Returns the description to use, or None if the user aborted.
+fn confirm_new_cl_description(mut text: String, rules: &config::DescriptionRules) -> Result<Option<String>> {
+    loop {
+        text = normalize_description(&text);
+        if check_description(&text, rules) {
+            return Ok(Some(text));
+        }
+        
+        println!("\n(e)dit in $EDITOR, (c)reate anyway or (a)bort?");
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        match input.trim().to_lowercase().as_str() {
+            "e" | "edit" => text = edit_text_in_editor(&text, "new-cl")?,
+            "c" | "create" => return Ok(Some(text)),
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// Ask for the description of a new CL inline, or in $EDITOR starting from the template.
+/// Returns None if the user aborted.
+fn prompt_new_cl_description() -> Result<Option<String>> {
+    let rules = config::get_description_rules()?;
+    let template = description::render_template(&rules.template);
+    
+    if template.trim().is_empty() {
+        println!("(a lone '.' on the first line opens $EDITOR instead)");
+    } else {
+        println!("Description template:");
+        for line in template.lines() {
+            println!("  {}", line.bright_black());
+        }
+        println!("(a lone '.' on the first line opens $EDITOR with the template instead)");
+    }
+    println!();
+    
+    let mut text = prompt_description_inline()?;
+    if text.trim().is_empty() {
+        text = edit_text_in_editor(&template, "new-cl")?;
+    }
+    
+    confirm_new_cl_description(text, &rules)
+}
+
+/// Prompt for a description and create a tracked CL with it, moving `files` (depot paths opened
+/// in the default changelist) into it. Returns None if the user aborted.
+fn create_changelist_interactive(files: &[String]) -> Result<Option<String>> {
+    let desc = match prompt_new_cl_description()? {
+        Some(desc) => desc,
+        None => return Ok(None),
+    };
+    let new_cl = perforce::create_changelist(&desc, files)?;
+    add_tracked_cl(&new_cl)?;
+    Ok(Some(new_cl))
+}
+
+/// Edit only the description of a CL, validate it and write it back with `p4 change -i`.
+/// Returns true if the description was changed.
+fn edit_description(cl: &str, inline: bool) -> Result<bool> {
+    let current = match perforce::get_change_description(cl)? {
+        Some(desc) => desc,
+        None => anyhow::bail!("CL {} does not exist", cl),
+    };
+    
+    let rules = config::get_description_rules()?;
+    
+    // CLs created before templates existed only have p4's placeholder: start from the template instead
+    let mut text = if current.is_empty() || current == "<enter description here>" {
+        description::render_template(&rules.template)
+    } else {
+        current.clone()
+    };
+    loop {
+        text = if inline {
+            println!("Current description of CL {}:", cl.bright_cyan().bold());
+            for line in text.lines() {
+                println!("  {}", line.bright_cyan());
+            }
+            println!();
+            prompt_description_inline()?
+        } else {
+            edit_text_in_editor(&text, &format!("describe-{}", cl))?
+        };
+        
+        text = normalize_description(&text);
+        
+        if text == current {
+            println!("Description unchanged.");
+            return Ok(false);
+        }
+        
+        if check_description(&text, &rules) {
+            break;
+        }
+        
+        println!("\n(e)dit again, (s)ave anyway or (a)bort?");
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        match input.trim().to_lowercase().as_str() {
+            "e" | "edit" => continue,
+            "s" | "save" => break,
+            _ => {
+                println!("Aborted, description not changed.");
+                return Ok(false);
+            }
+        }
+    }
+    
+    perforce::set_change_description(cl, &text)?;
+    println!("{}", format!("✓ Updated description of CL {}", cl).bright_green());
+    Ok(true)
+}
+
+fn cmd_new(message: &[String], file_paths: &[String]) -> Result<()> {
+    let files = expand_file_args(file_paths)?;
+    if !file_paths.is_empty() && files.is_empty() {
+        eprintln!("Error: No valid files found");
+        return Ok(());
+    }
+    
+    // Like git commit, each -m is a paragraph
+    let desc = if message.is_empty() {
+        prompt_new_cl_description()?
+    } else {
+        confirm_new_cl_description(message.join("\n\n"), &config::get_description_rules()?)?
+    };
+    let desc = match desc {
+        Some(desc) => desc,
+        None => {
+            println!("Cancelled.");
+            return Ok(());
+        }
+    };
+    
+    // Files already opened in the default changelist can be moved in by the change spec itself;
+    // files opened elsewhere are reopened, and the rest are opened for edit (or add if new)
+    let opened = perforce::get_opened_files()?;
+    let mut from_default: Vec<String> = Vec::new();
+    let mut to_open: Vec<(String, Option<String>)> = Vec::new();
+    for file in &files {
+        let depot = perforce::get_depot_path(file)?;
+        let opened_in = depot
+            .as_ref()
+            .and_then(|d| opened.iter().find(|f| &f.depot_file == d))
+            .map(|f| f.changelist.clone());
+        match (depot, opened_in) {
+            (Some(depot), Some(cl)) if cl == "default" => from_default.push(depot),
+            (depot, Some(_)) => to_open.push((file.clone(), depot)),
+            (_, None) => to_open.push((file.clone(), None)),
+        }
+    }
+    
+    let new_cl = perforce::create_changelist(&desc, &from_default)?;
+    add_tracked_cl(&new_cl)?;
+    println!("{}", format!("✓ Created CL {}", new_cl).bright_green());
+    for depot in &from_default {
+        println!("{} {} (moved from default)", "✓".bright_green(), depot);
+    }
+    
+    for (file, reopen_depot) in &to_open {
+        let output = match reopen_depot {
+            Some(depot) => std::process::Command::new("p4").args(["reopen", "-c", &new_cl, depot]).output()?,
+            None => {
+                let output = std::process::Command::new("p4").args(["edit", "-c", &new_cl, file]).output()?;
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                if stderr.contains("not on client") || stderr.contains("no such file") {
+                    std::process::Command::new("p4").args(["add", "-c", &new_cl, file]).output()?
+                } else {
+                    output
+                }
+            }
+        };
+        
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if output.status.success() && stderr.trim().is_empty() {
+            println!("{} {}", "✓".bright_green(), String::from_utf8_lossy(&output.stdout).trim());
+        } else {
+            println!("{} {}: {}", "✗".bright_red(), file, stderr.trim());
+        }
+    }
+    
+    Ok(())
+}
+
+/// Outcome of one pre-submit check.
+struct SubmitCheck {
+    name: String,
+    passed: bool,
+    blocking: bool, // p4 would refuse the submit anyway, so there's no "submit anyway"
+    details: Vec<String>,
+}
+
+const SHELF_CHECK: &str = "Shelf matches the workspace";
+
+/// Compare the shelved files of a CL with the workspace. Returns one line per difference.
+fn shelf_differences(cl: &str, opened: &[perforce::OpenedFile], shelved: &[perforce::OpenedFile]) -> Result<Vec<String>> {
+    let mut differences = Vec::new();
+    
+    for f in shelved {
+        if !opened.iter().any(|o| o.depot_file == f.depot_file) {
+            differences.push(format!("only in shelf: {}", f.depot_file));
+        }
+    }
+    for f in opened {
+        let shelf = match shelved.iter().find(|s| s.depot_file == f.depot_file) {
+            Some(shelf) => shelf,
+            None => {
+                differences.push(format!("not shelved: {}", f.depot_file));
+                continue;
+            }
+        };
+        if f.action.contains("delete") || shelf.action.contains("delete") {
+            if f.action != shelf.action {
+                differences.push(format!("{} in workspace, {} in shelf: {}", f.action, shelf.action, f.depot_file));
+            }
+            continue;
+        }
+        let local = perforce::get_local_path(&f.depot_file)?.and_then(|path| std::fs::read(path).ok());
+        let shelved_content = perforce::print_file(&format!("{}@={}", f.depot_file, cl)).ok();
+        if local.is_none() || local != shelved_content {
+            differences.push(format!("differs from shelf: {}", f.depot_file));
+        }
+    }
+    
+    Ok(differences)
+}
+
+/// Run the pre-submit checks for a CL: description rules, file states, shelf and local hooks.
+fn run_submit_checks(cl: &str, opened: &[perforce::OpenedFile]) -> Result<Vec<SubmitCheck>> {
+    let mut checks = Vec::new();
+    
+    checks.push(SubmitCheck {
+        name: "Has opened files".to_string(),
+        passed: !opened.is_empty(),
+        blocking: true,
+        details: Vec::new(),
+    });
+    
+    let desc = perforce::get_change_description(cl)?.unwrap_or_default();
+    let problems = description::validate(&desc, &config::get_description_rules()?);
+    checks.push(SubmitCheck {
+        name: "Description follows the team rules".to_string(),
+        passed: problems.is_empty(),
+        blocking: false,
+        details: problems,
+    });
+    
+    let states = perforce::get_opened_file_states()?;
+    let cl_states: Vec<_> = states.iter().filter(|f| f.changelist == cl).collect();
+    let unresolved: Vec<String> = cl_states.iter().filter(|f| f.unresolved).map(|f| f.depot_file.clone()).collect();
+    checks.push(SubmitCheck {
+        name: "No files need resolve".to_string(),
+        passed: unresolved.is_empty(),
+        blocking: true,
+        details: unresolved,
+    });
+    let out_of_date: Vec<String> = cl_states
+        .iter()
+        .filter(|f| f.is_out_of_date())
+        .map(|f| format!("{}#{} (head #{})", f.depot_file,
+            f.have_rev.as_deref().unwrap_or("?"), f.head_rev.as_deref().unwrap_or("?")))
+        .collect();
+    checks.push(SubmitCheck {
+        name: "Files are up to date with head".to_string(),
+        passed: out_of_date.is_empty(),
+        blocking: true,
+        details: out_of_date,
+    });
+    
+    let shelved = perforce::get_shelved_files(cl).unwrap_or_default();
+    if !shelved.is_empty() {
+        let differences = shelf_differences(cl, opened, &shelved)?;
+        checks.push(SubmitCheck {
+            name: SHELF_CHECK.to_string(),
+            passed: differences.is_empty(),
+            blocking: false,
+            details: differences,
+        });
+    }
+    
+    // Hooks can take a while, don't bother if p4 would refuse the submit anyway
+    let blocked = checks.iter().any(|c| c.blocking && !c.passed);
+    for hook in config::get_submit_hooks()? {
+        if blocked {
+            checks.push(SubmitCheck {
+                name: format!("{} (skipped)", hook.name),
+                passed: false,
+                blocking: false,
+                details: Vec::new(),
+            });
+            continue;
+        }
+        
+        let command = hook.command.replace("{cl}", cl);
+        println!("{}", format!("Running {}: {}", hook.name, command).bright_black());
+        let status = std::process::Command::new("sh").arg("-c").arg(&command).status()?;
+        checks.push(SubmitCheck {
+            name: hook.name.clone(),
+            passed: status.success(),
+            blocking: false,
+            details: if status.success() { Vec::new() } else { vec![format!("`{}` failed ({})", command, status)] },
+        });
+    }
+    
+    Ok(checks)
+}
+
+/// Run the pre-submit checks, confirm and submit a CL. Returns true if it was submitted.
+fn submit_with_checks(cl: &str) -> Result<bool> {
+    let opened: Vec<_> = perforce::get_opened_files()?
+        .into_iter()
+        .filter(|f| f.changelist == cl)
+        .collect();
+    
+    println!("Running pre-submit checks for CL {}...\n", cl.bright_cyan().bold());
+    let checks = run_submit_checks(cl, &opened)?;
+    
+    println!("\n{}", "Pre-submit summary:".bold());
+    for check in &checks {
+        if check.passed {
+            println!("  {} {}", "✓".bright_green(), check.name);
+        } else if check.blocking {
+            println!("  {} {}", "✗".bright_red(), check.name.bright_red());
+        } else {
+            println!("  {} {}", "!".bright_yellow(), check.name.bright_yellow());
+        }
+        for detail in &check.details {
+            println!("      {}", detail);
+        }
+    }
+    println!();
+    
+    if checks.iter().any(|c| c.blocking && !c.passed) {
+        println!("{}", "✗ Fix the problems above before submitting.".bright_red());
+        return Ok(false);
+    }
+    
+    // p4 refuses to submit a change that still has shelved files, so the shelf has to go first
+    let shelved = perforce::get_shelved_files(cl).unwrap_or_default();
+    if !shelved.is_empty() {
+        println!("The {} shelved file(s) of CL {} will be deleted before submitting.", shelved.len(), cl);
+    }
+    
+    let all_passed = checks.iter().all(|c| c.passed);
+    if all_passed {
+        println!("Submit {} file(s) in CL {}? (y/n):", opened.len(), cl);
+    } else {
+        println!("{}", "Some checks failed. Type 'yes' to submit anyway:".bright_yellow());
+    }
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    let answer = input.trim().to_lowercase();
+    let confirmed = if all_passed { answer == "y" || answer == "yes" } else { answer == "yes" };
+    if !confirmed {
+        println!("Submit cancelled.");
+        return Ok(false);
+    }
+    
+    // Deleting a shelf that differs from the workspace loses whatever is only in the shelf
+    let shelf_differs = checks.iter().any(|c| c.name == SHELF_CHECK && !c.passed);
+    if shelf_differs {
+        println!("{}", "The shelf has changes that are not in the workspace (see above), they will be lost.".bright_red());
+        println!("Type 'delete shelf' to delete it and submit:");
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        if input.trim().to_lowercase() != "delete shelf" {
+            println!("Submit cancelled. Unshelve or reshelve CL {} first to keep the shelved changes.", cl);
+            return Ok(false);
+        }
+    }
+    
+    if !shelved.is_empty() {
+        println!("Deleting shelved files of CL {}...", cl);
+        perforce::delete_shelf(cl)?;
+    }
+    
+    println!("Submitting CL {}...", cl);
+    let submitted = match perforce::submit_changelist(cl) {
+        Ok(submitted) => submitted,
+        Err(e) if !shelved.is_empty() => {
+            // Put the shelf back, so a failed submit doesn't lose it
+            println!("{}", "Submit failed, shelving the workspace files again...".bright_yellow());
+            match perforce::shelve_changelist(cl) {
+                Ok(_) => config::mark_shelved(cl)?,
+                Err(shelve_error) => println!("{} Failed to reshelve CL {}: {}", "✗".bright_red(), cl, shelve_error),
+            }
+            return Err(e);
+        }
+        Err(e) => return Err(e),
+    };
+    remove_tracked_cl(cl)?;
+    
+    if submitted == cl {
+        println!("\n{}", format!("✓ Submitted CL {}", cl).bright_green());
+    } else {
+        println!("\n{}", format!("✓ Submitted CL {} as CL {}", cl, submitted).bright_green());
+    }
+    Ok(true)
+}
+
+fn cmd_submit(cl: Option<&str>) -> Result<()> {
+    let cl = match cl {
+        Some(cl) => cl.to_string(),
+        None => {
+            // The default changelist can't be submitted by number, only numbered CLs are offered
+            let opened = perforce::get_opened_files()?;
+            let mut cls: Vec<String> = opened
+                .iter()
+                .map(|f| f.changelist.clone())
+                .filter(|c| c != "default")
+                .collect::<std::collections::HashSet<_>>()
+                .into_iter()
+                .collect();
+            cls.sort_by_key(|c| c.parse::<i64>().unwrap_or(0));
+            
+            if cls.is_empty() {
+                println!("No numbered changelists with opened files found.");
+                return Ok(());
+            }
+            
+            let mut descriptions: HashMap<String, String> = HashMap::new();
+            for cl in &cls {
+                if let Ok(Some(desc)) = perforce::get_change_description(cl) {
+                    let first_line = desc.lines().next().unwrap_or("").trim();
+                    descriptions.insert(cl.clone(), first_line.to_string());
+                }
+            }
+            
+            println!("Select a changelist to submit:");
+            println!();
+            match interactive_select_with_desc(&cls, &descriptions)? {
+                Some(cl) => cl,
+                None => {
+                    println!("No changelist selected.");
+                    return Ok(());
+                }
+            }
+        }
+    };
+    
+    if cl.parse::<i64>().is_err() {
+        println!("Error: Invalid CL number '{}'", cl);
+        return Ok(());
+    }
+    
+    println!();
+    submit_with_checks(&cl)?;
+    Ok(())
+}
+
+/// What the user decided to do in the merge viewer.
+enum MergeAction {
+    Quit,
+    Write,               // write the merge result and accept it
+    Edit,                // edit the merge result in $EDITOR, then accept it
+    Accept(&'static str), // let p4 resolve the whole file: "y", "t" or "m"
+}
+
+/// Pad or truncate plain text to exactly `width` columns.
+fn fit_to_width(s: &str, width: usize) -> String {
+    let s = truncate_to_width(s, width);
+    let pad = width.saturating_sub(unicode_width::UnicodeWidthStr::width(s.as_str()));
+    format!("{}{}", s, " ".repeat(pad))
+}
+
+/// Full-screen three-pane view (base | theirs | yours) of a merge, with per-hunk choices.
+/// Expects raw mode and the alternate screen.
+fn merge_viewer(file: &str, merge: &mut merge::Merge) -> Result<MergeAction> {
+    let mut stdout = std::io::stdout();
+    let mut hunk_idx = merge.hunks.iter().position(|h| h.choice.is_none()).unwrap_or(0);
+    let mut scroll = 0isize;
+    let mut message: Option<String> = None;
+    const CONTEXT: usize = 3;
+    
+    loop {
+        let (term_width, term_height) = terminal::size()?;
+        let pane_width = (term_width as usize).saturating_sub(2) / 3;
+        let visible_lines = (term_height as usize).saturating_sub(3); // Title, pane headers and status bar
+        let undecided = merge.hunks.iter().filter(|h| h.choice.is_none()).count();
+        
+        execute!(stdout, cursor::MoveTo(0, 0), terminal::Clear(ClearType::All))?;
+        
+        let hunk = merge.hunks.get(hunk_idx);
+        let title = match hunk {
+            Some(h) => format!(
+                "{}  hunk {}/{} [{}] → {}  ({} undecided)",
+                file,
+                hunk_idx + 1,
+                merge.hunks.len(),
+                match h.kind {
+                    merge::HunkKind::Yours => "changed by you",
+                    merge::HunkKind::Theirs => "changed by them",
+                    merge::HunkKind::Same => "same change on both sides",
+                    merge::HunkKind::Conflict => "conflict",
+                },
+                match h.choice {
+                    Some(merge::Choice::Yours) => "yours",
+                    Some(merge::Choice::Theirs) => "theirs",
+                    Some(merge::Choice::Both) => "both",
+                    None => "undecided",
+                },
+                undecided
+            ),
+            None => format!("{}  (no differences)", file),
+        };
+        write!(stdout, "{}\r\n", truncate_to_width(&title, term_width as usize).bold())?;
+        
+        // Mark the side(s) the current hunk takes
+        let chosen = |side: merge::Choice| {
+            hunk.and_then(|h| h.choice).map(|c| c == side || c == merge::Choice::Both).unwrap_or(false)
+        };
+        let headers = [
+            ("BASE", false),
+            ("THEIRS", chosen(merge::Choice::Theirs)),
+            ("YOURS", chosen(merge::Choice::Yours)),
+        ];
+        let header_line = headers
+            .iter()
+            .map(|(name, taken)| {
+                let text = fit_to_width(&format!(" {}{}", name, if *taken { " ✓" } else { "" }), pane_width);
+                if *taken { text.black().on_green().to_string() } else { text.black().on_white().to_string() }
+            })
+            .join("│");
+        write!(stdout, "{}\r\n", header_line)?;
+        
+        // Each pane shows its side of the current hunk with some context
+        type Pane<'a> = (&'a [String], std::ops::Range<usize>, fn(&str) -> String);
+        let panes: [Pane; 3] = match hunk {
+            Some(h) => [
+                (&merge.base, h.base.clone(), |s| s.bright_yellow().to_string()),
+                (&merge.theirs, h.theirs.clone(), |s| s.bright_cyan().to_string()),
+                (&merge.yours, h.yours.clone(), |s| s.bright_green().to_string()),
+            ],
+            None => [
+                (&merge.base, 0..0, |s| s.to_string()),
+                (&merge.theirs, 0..0, |s| s.to_string()),
+                (&merge.yours, 0..0, |s| s.to_string()),
+            ],
+        };
+        
+        for row in 0..visible_lines {
+            let cells: Vec<String> = panes
+                .iter()
+                .map(|(lines, range, highlight)| {
+                    let start = range.start as isize - CONTEXT as isize + scroll;
+                    let idx = start + row as isize;
+                    if idx < 0 || idx as usize >= lines.len() {
+                        return " ".repeat(pane_width);
+                    }
+                    let idx = idx as usize;
+                    let text = lines[idx].trim_end_matches(['\n', '\r']).replace('\t', "    ");
+                    let cell = fit_to_width(&format!("{:>5} {}", idx + 1, text), pane_width);
+                    if range.contains(&idx) {
+                        highlight(&cell)
+                    } else {
+                        cell
+                    }
+                })
+                .collect();
+            write!(stdout, "{}\r\n", cells.join("│"))?;
+        }
+        
+        // Status bar
+        let status = match message.take() {
+            Some(msg) => msg,
+            None => "n/p:hunk ↑↓:scroll y/t/b:take yours/theirs/both u:undo w:write e:edit Y/T/M:p4 accept yours/theirs/merge q:back".to_string(),
+        };
+        execute!(stdout, cursor::MoveTo(0, term_height.saturating_sub(1)))?;
+        write!(stdout, "{}", fit_to_width(&status, term_width as usize).black().on_white())?;
+        stdout.flush()?;
+        
+        if let Event::Key(KeyEvent { code, .. }) = event::read()? {
+            let choice = match code {
+                KeyCode::Char('y') => Some(merge::Choice::Yours),
+                KeyCode::Char('t') => Some(merge::Choice::Theirs),
+                KeyCode::Char('b') => Some(merge::Choice::Both),
+                _ => None,
+            };
+            if let (Some(choice), Some(h)) = (choice, merge.hunks.get_mut(hunk_idx)) {
+                h.choice = Some(choice);
+                // Move on to the next conflict still needing a decision
+                if let Some(next) = merge.hunks.iter().position(|h| h.choice.is_none()) {
+                    hunk_idx = next;
+                    scroll = 0;
+                }
+                continue;
+            }
+            
+            match code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(MergeAction::Quit),
+                KeyCode::Char('n') | KeyCode::Right | KeyCode::Tab if hunk_idx + 1 < merge.hunks.len() => {
+                    hunk_idx += 1;
+                    scroll = 0;
+                }
+                KeyCode::Char('p') | KeyCode::Left | KeyCode::BackTab if hunk_idx > 0 => {
+                    hunk_idx -= 1;
+                    scroll = 0;
+                }
+                KeyCode::Down | KeyCode::Char('j') => scroll += 1,
+                KeyCode::Up | KeyCode::Char('k') => scroll -= 1,
+                KeyCode::PageDown | KeyCode::Char(' ') => scroll += visible_lines as isize,
+                KeyCode::PageUp => scroll -= visible_lines as isize,
+                KeyCode::Char('u') => {
+                    if let Some(h) = merge.hunks.get_mut(hunk_idx) {
+                        if h.kind == merge::HunkKind::Conflict {
+                            h.choice = None;
+                        }
+                    }
+                }
+                KeyCode::Char('w') => {
+                    if merge.is_decided() {
+                        return Ok(MergeAction::Write);
+                    }
+                    message = Some(format!("{} conflict(s) still undecided: pick a side for each, or use e to edit", undecided));
+                }
+                KeyCode::Char('e') => return Ok(MergeAction::Edit),
+                KeyCode::Char('Y') => return Ok(MergeAction::Accept("y")),
+                KeyCode::Char('T') => return Ok(MergeAction::Accept("t")),
+                KeyCode::Char('M') => return Ok(MergeAction::Accept("m")),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Resolve one file: three-pane merge for text content, accept yours/theirs for anything else.
+fn resolve_one(resolve: &perforce::PendingResolve) -> Result<()> {
+    let local = &resolve.client_file;
+    
+    let contents = if resolve.resolve_type.is_empty() || resolve.resolve_type == "content" {
+        let base = match resolve.base_spec() {
+            Some(spec) => perforce::print_file(&spec)?,
+            None => Vec::new(),
+        };
+        let theirs = perforce::print_file(&resolve.theirs_spec())?;
+        let yours = std::fs::read(local).with_context(|| format!("Failed to read {}", local))?;
+        // Binary files can't be merged line by line, and other encodings would be rewritten as UTF-8
+        // with replacement characters
+        if [&base, &theirs, &yours].iter().any(|c| c.contains(&0)) {
+            None
+        } else {
+            match (String::from_utf8(base), String::from_utf8(theirs), String::from_utf8(yours)) {
+                (Ok(base), Ok(theirs), Ok(yours)) => Some((base, theirs, yours)),
+                _ => None,
+            }
+        }
+    } else {
+        None
+    };
+    
+    let action = match contents {
+        Some((base, theirs, yours)) => {
+            let mut merge = merge::Merge::new(&base, &yours, &theirs);
+            
+            let mut stdout = std::io::stdout();
+            terminal::enable_raw_mode()?;
+            execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+            let action = merge_viewer(local, &mut merge);
+            execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+            terminal::disable_raw_mode()?;
+            
+            match action? {
+                MergeAction::Quit => {
+                    println!("Skipped {}", local);
+                    return Ok(());
+                }
+                MergeAction::Write => {
+                    write_file_atomic(local, merge.result().as_bytes())?;
+                    "y"
+                }
+                MergeAction::Edit => {
+                    let edited = edit_text_in_editor(&merge.result(), "merge")?;
+                    if edited.lines().any(|l| l.starts_with("<<<<<<< ") || l.starts_with(">>>>>>> ")) {
+                        println!("{}", format!("✗ Conflict markers left in the merge, {} not resolved", local).bright_red());
+                        return Ok(());
+                    }
+                    write_file_atomic(local, edited.as_bytes())?;
+                    "y"
+                }
+                MergeAction::Accept(mode) => mode,
+            }
+        }
+        None => {
+            println!("{} needs a {} resolve from {}", local.bright_cyan(), 
+                if resolve.resolve_type.is_empty() { "content" } else { &resolve.resolve_type }, resolve.theirs_spec());
+            println!("Accept (y)ours, (t)heirs or (s)kip?");
+            let mut input = String::new();
+            std::io::stdin().read_line(&mut input)?;
+            match input.trim().to_lowercase().as_str() {
+                "y" | "yours" => "y",
+                "t" | "theirs" => "t",
+                _ => {
+                    println!("Skipped {}", local);
+                    return Ok(());
+                }
+            }
+        }
+    };
+    
+    match perforce::resolve_file(local, action) {
+        Ok(_) => println!("{} {}", "✓ Resolved".bright_green(), local),
+        Err(e) => println!("{} {}", "✗".bright_red(), e),
+    }
+    Ok(())
+}
+
+fn cmd_resolve(paths: &[String]) -> Result<()> {
+    loop {
+        let resolves = perforce::get_pending_resolves(paths)?;
+        if resolves.is_empty() {
+            println!("{}", "✓ No files need resolve.".bright_green());
+            return Ok(());
+        }
+        
+        let items: Vec<String> = resolves.iter().map(|r| r.client_file.clone()).collect();
+        let descriptions: HashMap<String, String> = resolves
+            .iter()
+            .map(|r| {
+                let kind = if r.resolve_type.is_empty() { "content" } else { &r.resolve_type };
+                (r.client_file.clone(), format!("{} from {}", kind, r.theirs_spec()))
+            })
+            .collect();
+        
+        println!("Select a file to resolve ({} left):", resolves.len());
+        println!();
+        let selected = match interactive_select_with_desc(&items, &descriptions)? {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        
+        let resolve = resolves.iter().find(|r| r.client_file == selected).unwrap();
+        resolve_one(resolve)?;
+        println!();
+    }
+}
+
+/// A text progress bar like "[#########           ]  45% (450/1000)".
+fn render_progress_bar(done: usize, total: usize, width: usize) -> String {
+    let total = total.max(1);
+    let done = done.min(total);
+    let filled = width * done / total;
+    format!(
+        "[{}{}] {:>3}% ({}/{})",
+        "#".repeat(filled).bright_green(),
+        " ".repeat(width - filled),
+        100 * done / total,
+        done,
+        total
+    )
+}
+
+fn cmd_sync(paths: &[String], preview_only: bool) -> Result<()> {
+    println!("Checking what needs syncing...");
+    // Preview and sync the same change, so files submitted in between aren't synced unseen.
+    // Paths that already name a revision are left alone.
+    let head = perforce::get_head_change()?;
+    let paths: Vec<String> = if paths.is_empty() {
+        vec![format!("//...@{}", head)]
+    } else {
+        paths
+            .iter()
+            .map(|p| if p.contains(['#', '@']) { p.clone() } else { format!("{}@{}", p, head) })
+            .collect()
+    };
+    let preview = perforce::preview_sync(&paths)?;
+    if preview.is_empty() {
+        println!("{}", "✓ Files are up to date.".bright_green());
+        return Ok(());
+    }
+    
+    // Files we have opened will be scheduled for resolve instead of being overwritten
+    let opened: HashMap<String, String> = perforce::get_opened_files()?
+        .into_iter()
+        .map(|f| (f.depot_file, f.changelist))
+        .collect();
+    let count = |action: &str| preview.iter().filter(|f| f.action == action).count();
+    
+    println!(
+        "\n{} file(s) will change: {} updated, {} added, {} deleted",
+        preview.len(),
+        count("updated"),
+        count("added"),
+        count("deleted")
+    );
+    
+    let needs_resolve: Vec<_> = preview.iter().filter(|f| opened.contains_key(&f.depot_file)).collect();
+    if !needs_resolve.is_empty() {
+        println!("\n{}", format!("{} opened file(s) will need resolve:", needs_resolve.len()).bright_yellow().bold());
+        for f in &needs_resolve {
+            println!("  {} {}#{} (opened in {})", "!".bright_yellow(), f.depot_file.bright_yellow(), f.rev,
+                if opened[&f.depot_file] == "default" { "default CL".to_string() } else { format!("CL {}", opened[&f.depot_file]) });
+        }
+    }
+    
+    // Don't flood the terminal when syncing large trees
+    const MAX_LISTED: usize = 30;
+    println!();
+    for f in preview.iter().filter(|f| !opened.contains_key(&f.depot_file)).take(MAX_LISTED) {
+        let padded = format!("{:<8}", f.action);
+        let action = match f.action.as_str() {
+            "added" => padded.bright_green().to_string(),
+            "deleted" => padded.bright_red().to_string(),
+            _ => padded.bright_cyan().to_string(),
+        };
+        println!("  {} {}#{}", action, f.depot_file, f.rev);
+    }
+    let unlisted = (preview.len() - needs_resolve.len()).saturating_sub(MAX_LISTED);
+    if unlisted > 0 {
+        println!("  ... and {} more", unlisted);
+    }
+    
+    if preview_only {
+        return Ok(());
+    }
+    
+    println!("\nSync {} file(s) to change {}? (y/n):", preview.len(), head);
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    let answer = input.trim().to_lowercase();
+    if answer != "y" && answer != "yes" {
+        println!("Cancelled.");
+        return Ok(());
+    }
+    
+    let (mut updated, mut added, mut deleted) = (0, 0, 0);
+    let mut done = 0;
+    let errors = perforce::sync_with_progress(&paths, |line| {
+        if line.contains(" - updating ") || line.contains(" - refreshing ") || line.contains(" - replacing ") {
+            updated += 1;
+        } else if line.contains(" - added as ") {
+            added += 1;
+        } else if line.contains(" - deleted as ") {
+            deleted += 1;
+        } else {
+            // e.g. "... - must resolve #4 before submitting"
+            return;
+        }
+        done += 1;
+        
+        let (term_width, _) = terminal::size().unwrap_or((80, 24));
+        let bar = render_progress_bar(done, preview.len(), 30);
+        let file = line.split(" - ").next().unwrap_or(line);
+        let room = (term_width as usize).saturating_sub(visual_width(&bar) + 2);
+        print!("\r{} {}", bar, fit_to_width(file, room));
+        std::io::stdout().flush().ok();
+    })?;
+    println!();
+    
+    println!("\n{}", format!("✓ Synced {} file(s): {} updated, {} added, {} deleted", done, updated, added, deleted).bright_green());
+    if !errors.is_empty() {
+        println!("{}", format!("✗ {} problem(s):", errors.len()).bright_red());
+        for error in &errors {
+            println!("  {}", error);
+        }
+    }
+    
+    let resolves = perforce::get_pending_resolves(&[])?;
+    if !resolves.is_empty() {
+        println!("\n{}", format!("{} file(s) need resolve. Run p resolve now? (y/n):", resolves.len()).bright_yellow());
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        let answer = input.trim().to_lowercase();
+        if answer == "y" || answer == "yes" {
+            println!();
+            cmd_resolve(&[])?;
+        }
+    }
+    
+    Ok(())
+}
+
+fn cmd_status(path: Option<&str>) -> Result<()> {
+    // Scope to the current directory by default, like git status
+    let scope = match path {
+        Some(p) if std::path::Path::new(p).is_dir() => format!("{}/...", p.trim_end_matches('/')),
+        Some(p) => p.to_string(),
+        None => "./...".to_string(),
+    };
+    
+    println!("Checking {} for changes not opened in Perforce...", scope);
+    let changes = perforce::reconcile_preview(&scope)?;
+    if changes.is_empty() {
+        println!("{}", "✓ Nothing to reconcile: all changes are opened.".bright_green());
+        return Ok(());
+    }
+    
+    // Group by what reconcile would do, reusing the changelist field for the group name
+    let groups = [
+        ("edit", "modified", "Modified without being opened", (|s| s.bright_yellow().to_string()) as fn(&str) -> String),
+        ("add", "new", "New files not in the depot", |s| s.bright_green().to_string()),
+        ("delete", "deleted", "Deleted without being opened", |s| s.bright_red().to_string()),
+    ];
+    let mut files: Vec<perforce::OpenedFile> = Vec::new();
+    let mut group_colors: HashMap<String, fn(&str) -> String> = HashMap::new();
+    let mut group_descriptions: HashMap<String, String> = HashMap::new();
+    for f in &changes {
+        let (_, name, description, color) = groups
+            .iter()
+            .find(|(action, ..)| f.action.ends_with(action))
+            .copied()
+            .unwrap_or(("", "other", "Other changes", |s| s.bright_cyan().to_string()));
+        group_colors.insert(name.to_string(), color);
+        group_descriptions.insert(name.to_string(), description.to_string());
+        files.push(perforce::OpenedFile { changelist: name.to_string(), ..f.clone() });
+    }
+    
+    for (_, name, description, color) in &groups {
+        let count = files.iter().filter(|f| &f.changelist == name).count();
+        if count > 0 {
+            println!("  {} {}", color(&format!("{:>4}", count)), description);
+        }
+    }
+    println!();
+    
+    let selected_files = interactive_file_select(&files, &group_colors, &group_descriptions, false, None)?;
+    if selected_files.is_empty() {
+        println!("No files selected.");
+        return Ok(());
+    }
+    
+    let selected_cl = match select_target_cl(&format!("Select a changelist to open {} file(s) in:", selected_files.len()))? {
+        Some(cl) => cl,
+        None => return Ok(()),
+    };
+    
+    println!("\nOpening files...");
+    let mut success_count = 0;
+    let mut error_count = 0;
+    for f in &selected_files {
+        match perforce::reconcile_file(&selected_cl, &f.depot_file) {
+            Ok(output) => {
+                println!("{} {}", "✓".bright_green(), if output.is_empty() { &f.depot_file } else { &output });
+                success_count += 1;
+            }
+            Err(e) => {
+                println!("{} {}: {}", "✗".bright_red(), f.depot_file, e);
+                error_count += 1;
+            }
+        }
+    }
+    
+    println!();
+    if success_count > 0 {
+        println!("{}", format!("✓ {} file(s) opened successfully", success_count).bright_green());
+    }
+    if error_count > 0 {
+        eprintln!("{}", format!("✗ {} file(s) failed to open", error_count).bright_red());
+    }
+    
+    Ok(())
+}
+
+fn cmd_describe_edit(cl: &str, inline: bool) -> Result<()> {
+    if cl.parse::<i64>().is_err() {
+        println!("Error: Invalid CL number '{}'", cl);
+        return Ok(());
+    }
+    edit_description(cl, inline)?;
+    Ok(())
+}
+
+/// Where `p annotate` shows the annotation.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum AnnotateOutput {
+    Viewer,
+    Porcelain,
+    Json,
+}
+
+/// One line of `p annotate --json` output.
+#[derive(serde::Serialize)]
+struct AnnotateRecord<'a> {
+    line: usize,               // 1-based line number in the annotated file
+    change: Option<&'a str>,    // CL that introduced the line, null if not committed
+    origin: &'static str,       // "submitted", "shelved" or "local"
+    user: &'a str,
+    date: &'a str,              // yyyy/mm/dd
+    orig_line: Option<usize>,   // 1-based line number in the file as of `change`, null if not known
+    content: &'a str,
+}
+
+/// Print an annotation for editor integrations. Only lines present in the annotated file are printed,
+/// numbered from 1 in that file, with the line number they had in the CL that introduced them.
+///
+/// Porcelain: one record per line, fields separated by tabs, content last and unescaped:
+///   <line>\t<CL>\t<origin>\t<user>\t<date>\t<orig_line>\t<content>
+/// Origin is "submitted" for lines from a submitted CL, "shelved" for content of the annotated shelf
+/// (`file@=CL`) and "local" for edits in the workspace file. CL is "-" unless the origin is "submitted".
+/// The date is yyyy/mm/dd: when the CL was submitted, when the shelf was last updated, or when the
+/// workspace file was last modified. The original line number is "-" when it can't be found: for
+/// lines not submitted, or brought in from another file by an integration.
+///
+/// JSON: {"file": "//depot/...", "revision": "@123" or null,
+///        "lines": [{"line", "change" (null if not submitted), "origin", "user", "date",
+///                   "orig_line" (null if not known), "content"}]}
+fn print_annotate_records(
+    lines: &[perforce::AnnotateLine],
+    depot_file: &str,
+    revision: Option<&str>,
+    output: AnnotateOutput,
+) -> Result<()> {
+    let present: Vec<&perforce::AnnotateLine> = lines.iter().filter(|line| line.present).collect();
+    let orig_lines = original_line_numbers(&present, depot_file);
+    let records: Vec<AnnotateRecord> = present
+        .iter()
+        .zip(orig_lines)
+        .enumerate()
+        .map(|(idx, (line, orig_line))| AnnotateRecord {
+            line: idx + 1,
+            change: annotate_line_cl(line),
+            origin: match annotate_line_cl(line) {
+                Some(_) => "submitted",
+                None if revision.is_some_and(|rev| rev.starts_with("@=")) => "shelved",
+                None => "local",
+            },
+            user: &line.username,
+            date: line.date.trim(),
+            orig_line,
+            content: &line.line_content,
+        })
+        .collect();
+    
+    let mut stdout = std::io::stdout().lock();
+    if output == AnnotateOutput::Json {
+        let json = serde_json::json!({ "file": depot_file, "revision": revision, "lines": records });
+        writeln!(stdout, "{}", json)?;
+    } else {
+        for record in &records {
+            writeln!(
+                stdout,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                record.line,
+                record.change.unwrap_or("-"),
+                record.origin,
+                record.user,
+                record.date,
+                record.orig_line.map_or("-".to_string(), |n| n.to_string()),
+                record.content
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// The 1-based line number each line had in the file as of the CL that introduced it, found by diffing
+/// the two like reblame does. None for lines not submitted, or not in that revision of this file
+/// (brought in by an integration from another file).
+fn original_line_numbers(present: &[&perforce::AnnotateLine], depot_file: &str) -> Vec<Option<usize>> {
+    let mut cls: Vec<&str> = present.iter().filter_map(|line| annotate_line_cl(line)).collect();
+    cls.sort_unstable();
+    cls.dedup();
+    
+    // One print per CL, a few at a time
+    const PARALLEL: usize = 8;
+    let revisions: HashMap<&str, Vec<String>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = cls
+            .chunks(cls.len().div_ceil(PARALLEL).max(1))
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .filter_map(|&cl| {
+                            let content = perforce::print_file(&format!("{}@{}", depot_file, cl)).ok()?;
+                            Some((cl, String::from_utf8_lossy(&content).lines().map(String::from).collect()))
+                        })
+                        .collect::<Vec<(&str, Vec<String>)>>()
+                })
+            })
+            .collect();
+        handles.into_iter().flat_map(|h| h.join().unwrap_or_default()).collect()
+    });
+    
+    let text: Vec<&str> = present.iter().map(|l| l.line_content.as_str()).collect();
+    let mut orig_lines = vec![None; present.len()];
+    for (cl, revision) in &revisions {
+        let revision_text: Vec<&str> = revision.iter().map(|l| l.as_str()).collect();
+        for op in similar::capture_diff_slices(similar::Algorithm::Myers, &text, &revision_text) {
+            if let similar::DiffOp::Equal { old_index, new_index, len } = op {
+                for offset in 0..len {
+                    if annotate_line_cl(present[old_index + offset]) == Some(cl) {
+                        orig_lines[old_index + offset] = Some(new_index + offset + 1);
+                    }
+                }
+            }
+        }
+    }
+    orig_lines
+}
+
+fn cmd_annotate(file_path: &str, options: &perforce::AnnotateOptions, output: AnnotateOutput) -> Result<()> {
+    // Split "path#rev" or "path@change/label/date" into the path and its revision specifier
+    let (path, revision) = match file_path.find(&['#', '@'][..]) {
+        Some(idx) => (&file_path[..idx], Some(&file_path[idx..])),
+        None => (file_path, None),
+    };
+    
+    // Describe panes and reblame refer to the file by depot path
+    let depot_file = if path.starts_with("//") {
+        path.to_string()
+    } else {
+        perforce::get_depot_path(path)?.unwrap_or_else(|| path.to_string())
+    };
+    
+    // Show loading indicator, unless stdout is for a program
+    let interactive = output == AnnotateOutput::Viewer;
+    if interactive {
+        print!("Loading annotate data");
+        std::io::stdout().flush()?;
+    }
+    
+    // Start spinner in a separate thread
+    let running = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(interactive));
+    let running_clone = running.clone();
+    
+    let spinner_thread = std::thread::spawn(move || {
+        if !running_clone.load(std::sync::atomic::Ordering::Relaxed) {
+            return;
+        }
+        let frames = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+        let mut idx = 0;
+        while running_clone.load(std::sync::atomic::Ordering::Relaxed) {
+            print!("\rLoading annotate data {}  ", frames[idx]);
+            std::io::stdout().flush().ok();
+            idx = (idx + 1) % frames.len();
+            std::thread::sleep(std::time::Duration::from_millis(80));
+        }
+        print!("\r{}\r", " ".repeat(50)); // Clear the line
+        std::io::stdout().flush().ok();
+    });
+    
+    // Fetch the data. Only the viewer shows deleted lines; records and overlays would drop them,
+    // so they skip the extra -a annotate.
+    let fetched = (|| -> Result<Vec<perforce::AnnotateLine>> {
+        match revision {
+            // p4 annotate can't read shelves: annotate the revision the shelved file is based on
+            // and lay the shelved content over it
+            Some(rev) if rev.starts_with("@=") => {
+                let cl = &rev[2..];
+                let shelved = perforce::get_shelved_files(cl)?
+                    .into_iter()
+                    .find(|f| f.depot_file == depot_file)
+                    .ok_or_else(|| anyhow::anyhow!("{} is not shelved in CL {}", depot_file, cl))?;
+                let base = match shelved.workrev.as_deref() {
+                    Some(base_rev) if base_rev != "none" => {
+                        perforce::get_annotate(&format!("{}#{}", depot_file, base_rev), options, false)?
+                    }
+                    _ => Vec::new(), // Shelved add
+                };
+                let content = perforce::print_file(&format!("{}{}", depot_file, rev))?;
+                let shelved_on = perforce::get_shelf_date(cl)?;
+                Ok(overlay_edits(base, &String::from_utf8_lossy(&content), "Shelved", &shelved_on))
+            }
+            Some(rev) => perforce::get_annotate(&format!("{}{}", path, rev), options, interactive),
+            // For a workspace file, annotate the revision we have and lay local edits over it
+            None if std::path::Path::new(path).is_file() && !path.starts_with("//") => {
+                let lines = perforce::get_annotate(&format!("{}#have", path), options, false)?;
+                match std::fs::read_to_string(path) {
+                    Ok(content) => {
+                        let modified = std::fs::metadata(path)
+                            .and_then(|m| m.modified())
+                            .map(|t| chrono::DateTime::<chrono::Local>::from(t).format("%Y/%m/%d").to_string())
+                            .unwrap_or_default();
+                        Ok(overlay_edits(lines, &content, "Not committed", &modified))
+                    }
+                    Err(_) => Ok(lines), // Binary or unreadable: show the annotation as is
+                }
+            }
+            None => perforce::get_annotate(path, options, interactive),
+        }
+    })();
+    
+    // Stop spinner
+    running.store(false, std::sync::atomic::Ordering::Relaxed);
+    spinner_thread.join().ok();
+    
+    let lines = fetched?;
+    if !interactive {
+        return print_annotate_records(&lines, &depot_file, revision, output);
+    }
+    if lines.is_empty() {
+        println!("No annotate data for file: {}", file_path);
+        return Ok(());
+    }
+    
+    // Enter raw mode for interactive viewing
+    terminal::enable_raw_mode()?;
+    
+    let mut stdout = std::io::stdout();
+    execute!(stdout, terminal::Clear(ClearType::All), cursor::Hide)?;
+    
+    let result = annotate_viewer(lines, &depot_file, revision.map(|r| r.to_string()), options);
+    
+    // Clean up terminal state
+    execute!(stdout, cursor::Show)?;
+    terminal::disable_raw_mode()?;
+    
+    result
+}
+
+fn cmd_log(file_path: &str) -> Result<()> {
+    println!("Loading history of {}...", file_path);
+    let revisions = perforce::get_filelog(file_path)?;
+    if revisions.is_empty() {
+        println!("No history for file: {}", file_path);
+        return Ok(());
+    }
+    
+    terminal::enable_raw_mode()?;
+    let mut stdout = std::io::stdout();
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+    
+    let result = filelog_viewer(&revisions);
+    
+    execute!(stdout, terminal::LeaveAlternateScreen, cursor::Show)?;
+    terminal::disable_raw_mode()?;
+    
+    result
+}
+
+/// The revisions to diff in the history browser, older first: the two marked revisions, the marked
+/// one and the one under the cursor, or the one under the cursor and the previous revision of its file.
+fn filelog_diff_pair<'a>(
+    revisions: &'a [perforce::FileRevision],
+    marked: &[usize],
+    cursor: usize,
+) -> Option<(&'a perforce::FileRevision, &'a perforce::FileRevision)> {
+    let (a, b) = match marked {
+        [a, b] => (*a, *b),
+        [a] if *a != cursor => (*a, cursor),
+        _ => {
+            // Revisions of a file are listed newest first, so its previous revision comes next
+            let current = &revisions[cursor];
+            let previous = revisions.get(cursor + 1).filter(|r| r.depot_file == current.depot_file)?;
+            return Some((previous, current));
+        }
+    };
+    let change = |idx: usize| revisions[idx].change.parse::<u64>().unwrap_or(0);
+    if change(a) <= change(b) {
+        Some((&revisions[a], &revisions[b]))
+    } else {
+        Some((&revisions[b], &revisions[a]))
+    }
+}
+
+/// Scrollable history of a file. Expects raw mode to be enabled.
+fn filelog_viewer(revisions: &[perforce::FileRevision]) -> Result<()> {
+    let mut stdout = std::io::stdout();
+    
+    // A header row before the revisions of each depot file (there are several when following branches)
+    let mut rows: Vec<Option<usize>> = Vec::new();
+    for (idx, revision) in revisions.iter().enumerate() {
+        if idx == 0 || revisions[idx - 1].depot_file != revision.depot_file {
+            rows.push(None);
+        }
+        rows.push(Some(idx));
+    }
+    
+    let rev_width = revisions.iter().map(|r| r.rev.len() + 1).max().unwrap_or(2);
+    let change_width = revisions.iter().map(|r| r.change.len()).max().unwrap_or(0);
+    let action_width = revisions.iter().map(|r| r.action.len()).max().unwrap_or(0);
+    let user_width = revisions.iter().map(|r| r.user.len()).max().unwrap_or(0);
+    
+    let mut top_row = 0;
+    let mut cursor_row: usize = 1; // rows[0] is a header
+    let mut marked: Vec<usize> = Vec::new(); // revisions marked for diffing, at most two
+    let mut message: Option<String> = None;
+    
+    loop {
+        let (term_width, term_height) = terminal::size()?;
+        let visible_lines = (term_height as usize).saturating_sub(2); // Status bar and message line
+        if cursor_row < top_row {
+            top_row = cursor_row.saturating_sub(1); // Keep the file header in view when going up
+        }
+        top_row = scroll_to_row(top_row, cursor_row, visible_lines, rows.len());
+        
+        let shown = rows.iter().enumerate().skip(top_row).map(|(row, entry)| {
+            match entry {
+                None => {
+                    let file = &revisions[rows[row + 1].unwrap_or(0)].depot_file;
+                    truncate_to_width(file, term_width as usize).bright_blue().bold().to_string()
+                }
+                Some(idx) => {
+                    let r = &revisions[*idx];
+                    let mark = if marked.contains(idx) { "●" } else { " " };
+                    let line = format!(
+                        "{} {:>rev_w$} {:>change_w$} {:action_w$} {:user_w$} {} {}",
+                        mark,
+                        format!("#{}", r.rev),
+                        r.change,
+                        r.action,
+                        r.user,
+                        r.date,
+                        r.description.lines().next().unwrap_or("").trim(),
+                        rev_w = rev_width,
+                        change_w = change_width,
+                        action_w = action_width,
+                        user_w = user_width,
+                    );
+                    let line = truncate_to_width(&line, term_width as usize);
+                    if row == cursor_row {
+                        line.reversed().to_string()
+                    } else if marked.contains(idx) {
+                        line.bright_yellow().to_string()
+                    } else {
+                        line
+                    }
+                }
+            }
+        });
+        draw_list_rows(shown, visible_lines)?;
+        
+        // Status bar
+        let current = rows[cursor_row].unwrap_or(0);
+        let status = format!(
+            "Rev {}/{} | q:quit ↑↓:move Space:mark d:diff a:annotate Enter:describe",
+            current + 1,
+            revisions.len()
+        );
+        draw_status_bar(visible_lines as u16, &status)?;
+        draw_message_line(message.take().as_deref())?;
+        stdout.flush()?;
+        
+        // Move the cursor by `delta` revisions, skipping header rows
+        let step = |from: usize, delta: isize| -> usize {
+            let mut row = from;
+            let mut remaining = delta.unsigned_abs();
+            while remaining > 0 {
+                let next = if delta > 0 { row + 1 } else { row.wrapping_sub(1) };
+                match rows.get(next) {
+                    Some(Some(_)) => {
+                        row = next;
+                        remaining -= 1;
+                    }
+                    Some(None) => row = next, // header: keep going without counting it
+                    None => break,
+                }
+            }
+            // Stopped on the header at the very top: go to its first revision
+            while rows[row].is_none() {
+                row += 1;
+            }
+            row
+        };
+        
+        if let Event::Key(KeyEvent { code, .. }) = event::read()? {
+            match code {
+                KeyCode::Char('q') | KeyCode::Esc => break,
+                KeyCode::Down | KeyCode::Char('j') => cursor_row = step(cursor_row, 1),
+                KeyCode::Up | KeyCode::Char('k') => cursor_row = step(cursor_row, -1),
+                KeyCode::PageDown => cursor_row = step(cursor_row, visible_lines as isize),
+                KeyCode::PageUp => cursor_row = step(cursor_row, -(visible_lines as isize)),
+                KeyCode::Home | KeyCode::Char('g') => cursor_row = 1,
+                KeyCode::End | KeyCode::Char('G') => cursor_row = rows.len() - 1,
+                KeyCode::Char(' ') => {
+                    if let Some(pos) = marked.iter().position(|&m| m == current) {
+                        marked.remove(pos);
+                    } else {
+                        if marked.len() == 2 {
+                            marked.remove(0);
+                        }
+                        marked.push(current);
+                    }
+                }
+                KeyCode::Char('d') => {
+                    let Some((from, to)) = filelog_diff_pair(revisions, &marked, current) else {
+                        message = Some("No earlier revision of this file: mark two revisions with Space to diff them".to_string());
+                        continue;
+                    };
+                    let from_spec = format!("{}#{}", from.depot_file, from.rev);
+                    let to_spec = format!("{}#{}", to.depot_file, to.rev);
+                    match perforce::get_diff2(&from_spec, &to_spec) {
+                        Ok(diff) => {
+                            let mut lines = colorize_diff(&diff);
+                            if lines.iter().all(|l| l.starts_with("====") || l.trim().is_empty()) {
+                                lines.push("No content differences.".bright_black().to_string());
+                            }
+                            pager_viewer(&format!("{} → {}", from_spec, to_spec), &lines, &[])?;
+                        }
+                        Err(e) => message = Some(format!("Diff failed: {}", e)),
+                    }
+                }
+                KeyCode::Char('a') => {
+                    let revision = &revisions[current];
+                    let rev = format!("#{}", revision.rev);
+                    execute!(stdout, cursor::MoveTo(0, term_height.saturating_sub(1)), terminal::Clear(ClearType::CurrentLine))?;
+                    write!(stdout, "Loading annotate data for {}{}...", revision.depot_file, rev)?;
+                    stdout.flush()?;
+                    
+                    let options = perforce::AnnotateOptions::default();
+                    match perforce::get_annotate(&format!("{}{}", revision.depot_file, rev), &options, true) {
+                        Ok(lines) if !lines.is_empty() => {
+                            execute!(stdout, terminal::Clear(ClearType::All))?;
+                            annotate_viewer(lines, &revision.depot_file, Some(rev), &options)?;
+                        }
+                        Ok(_) => message = Some(format!("No annotate data for {}{}", revision.depot_file, rev)),
+                        Err(e) => message = Some(format!("Annotate failed: {}", e)),
+                    }
+                }
+                KeyCode::Enter => {
+                    let revision = &revisions[current];
+                    let describe_lines = build_describe_lines(&revision.change, Some(&revision.depot_file))?;
+                    pager_viewer(&format!("CL {}", revision.change), &describe_lines, &[])?;
+                }
+                _ => {}
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+fn cmd_changes(path: Option<&str>, max: usize, user: Option<&str>, since: Option<&str>, until: Option<&str>) -> Result<()> {
+    // Scope to the current directory by default, like p status
+    let scope = match path {
+        Some(p) if std::path::Path::new(p).is_dir() => format!("{}/...", p.trim_end_matches('/')),
+        Some(p) => p.to_string(),
+        None => "./...".to_string(),
+    };
+    // p4 dates are yyyy/mm/dd; accept yyyy-mm-dd too. A bare date means its midnight, so the
+    // until date is extended to its end to include the changes of that day.
+    let since = since.map(|d| d.replace('-', "/"));
+    let until = until.map(|d| d.replace('-', "/")).map(|d| if d.contains(':') { d } else { format!("{}:23:59:59", d) });
+    let file_spec = match (&since, &until) {
+        (Some(since), Some(until)) => format!("{}@{},@{}", scope, since, until),
+        (Some(since), None) => format!("{}@{},@now", scope, since),
+        (None, Some(until)) => format!("{}@{}", scope, until),
+        (None, None) => scope.clone(),
+    };
+    
+    println!("Loading changes of {}...", scope);
+    let changes = perforce::get_changes(&file_spec, max, user)?;
+    if changes.is_empty() {
+        println!("No submitted changes for: {}", file_spec);
+        return Ok(());
+    }
+    
+    terminal::enable_raw_mode()?;
+    let mut stdout = std::io::stdout();
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+    
+    let result = changes_viewer(&changes);
+    
+    execute!(stdout, terminal::LeaveAlternateScreen, cursor::Show)?;
+    terminal::disable_raw_mode()?;
+    
+    result
+}
+
+/// Filters of the changes browser, applied to the loaded changelists.
+#[derive(Default)]
+struct ChangesFilter {
+    user: Option<String>,
+    from: Option<String>, // yyyy/mm/dd, or a prefix such as yyyy/mm
+    to: Option<String>,
+    text: Option<regex::Regex>,
+}
+
+impl ChangesFilter {
+    fn matches(&self, change: &perforce::SubmittedChange) -> bool {
+        // Dates are yyyy/mm/dd, so they compare as strings; a partial "to" date includes the whole month or year
+        self.user.as_ref().is_none_or(|user| &change.user == user)
+            && self.from.as_ref().is_none_or(|from| change.date.as_str() >= from.as_str())
+            && self.to.as_ref().is_none_or(|to| change.date.get(..to.len()).unwrap_or(&change.date) <= to.as_str())
+            && self.text.as_ref().is_none_or(|text| text.is_match(&change.description))
+    }
+    
+    /// Short description of the active filters for the status bar, empty when there are none.
+    fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(user) = &self.user {
+            parts.push(format!("user={}", user));
+        }
+        if self.from.is_some() || self.to.is_some() {
+            parts.push(format!("date={}..{}", self.from.as_deref().unwrap_or(""), self.to.as_deref().unwrap_or("")));
+        }
+        if let Some(text) = &self.text {
+            parts.push(format!("/{}", text.as_str()));
+        }
+        parts.join(" ")
+    }
+}
+
+/// Scrollable list of submitted changelists. Expects raw mode to be enabled.
+fn changes_viewer(changes: &[perforce::SubmittedChange]) -> Result<()> {
+    let mut stdout = std::io::stdout();
+    
+    let change_width = changes.iter().map(|c| c.change.len()).max().unwrap_or(0);
+    let user_width = changes.iter().map(|c| c.user.len()).max().unwrap_or(0);
+    
+    let mut filter = ChangesFilter::default();
+    let mut shown: Vec<usize> = (0..changes.len()).collect();
+    let mut top = 0;
+    let mut cursor_pos = 0; // index into shown
+    let mut message: Option<String> = None;
+    
+    loop {
+        let (term_width, term_height) = terminal::size()?;
+        let visible_lines = (term_height as usize).saturating_sub(2); // Status bar and message line
+        cursor_pos = cursor_pos.min(shown.len().saturating_sub(1));
+        top = scroll_to_row(top, cursor_pos, visible_lines, shown.len());
+        
+        let mut rows: Vec<String> = shown.iter().enumerate().skip(top).take(visible_lines).map(|(pos, &idx)| {
+            let c = &changes[idx];
+            let line = format!(
+                "{:>change_w$} {:user_w$} {} {}",
+                c.change,
+                c.user,
+                c.date,
+                c.description.lines().next().unwrap_or("").trim(),
+                change_w = change_width,
+                user_w = user_width,
+            );
+            let line = truncate_to_width(&line, term_width as usize);
+            if pos == cursor_pos {
+                line.reversed().to_string()
+            } else {
+                line
+            }
+        }).collect();
+        if shown.is_empty() {
+            rows.push("No changelists match the filters.".bright_black().to_string());
+        }
+        draw_list_rows(rows, visible_lines)?;
+        
+        // Status bar
+        let filters = filter.summary();
+        let status = format!(
+            "CL {}/{}{} | q:quit ↑↓:move Enter:describe c:cherry-pick u:user t:dates /:text",
+            (cursor_pos + 1).min(shown.len()),
+            shown.len(),
+            if filters.is_empty() { String::new() } else { format!(" [{}]", filters) }
+        );
+        draw_status_bar(visible_lines as u16, &status)?;
+        draw_message_line(message.take().as_deref())?;
+        stdout.flush()?;
+        
+        let current = shown.get(cursor_pos).map(|&idx| &changes[idx]);
+        if let Event::Key(KeyEvent { code, .. }) = event::read()? {
+            let mut refilter = false;
+            match code {
+                KeyCode::Char('q') | KeyCode::Esc => break,
+                KeyCode::Down | KeyCode::Char('j') => cursor_pos += 1,
+                KeyCode::Up | KeyCode::Char('k') => cursor_pos = cursor_pos.saturating_sub(1),
+                KeyCode::PageDown => cursor_pos += visible_lines,
+                KeyCode::PageUp => cursor_pos = cursor_pos.saturating_sub(visible_lines),
+                KeyCode::Home | KeyCode::Char('g') => cursor_pos = 0,
+                KeyCode::End | KeyCode::Char('G') => cursor_pos = shown.len().saturating_sub(1),
+                KeyCode::Char('u') => {
+                    filter.user = prompt_input("User (empty clears): ")?;
+                    refilter = true;
+                }
+                KeyCode::Char('t') => {
+                    match prompt_input("Dates from..to, e.g. 2026/01/01..2026/02 (empty clears): ")? {
+                        Some(range) => {
+                            let range = range.replace('-', "/");
+                            let (from, to) = range.split_once("..").unwrap_or((&range, ""));
+                            let non_empty = |d: &str| Some(d.trim().to_string()).filter(|d| !d.is_empty());
+                            filter.from = non_empty(from);
+                            filter.to = non_empty(to);
+                        }
+                        None => {
+                            filter.from = None;
+                            filter.to = None;
+                        }
+                    }
+                    refilter = true;
+                }
+                KeyCode::Char('/') => {
+                    match prompt_input("Description (regex, empty clears): ")? {
+                        Some(query) => {
+                            // Smart case: case-sensitive only if the query has uppercase letters
+                            let case_insensitive = !query.chars().any(|c| c.is_uppercase());
+                            match regex::RegexBuilder::new(&query).case_insensitive(case_insensitive).build() {
+                                Ok(regex) => filter.text = Some(regex),
+                                Err(e) => message = Some(format!("Invalid regex: {}", e)),
+                            }
+                        }
+                        None => filter.text = None,
+                    }
+                    refilter = true;
+                }
+                KeyCode::Enter => {
+                    if let Some(change) = current {
+                        match build_describe_lines(&change.change, None) {
+                            Ok(describe_lines) => {
+                                let title = format!("CL {}", change.change);
+                                if pager_viewer(&title, &describe_lines, &[('c', "cherry-pick")])? == Some('c') {
+                                    cherry_pick_pane(&change.change)?;
+                                }
+                            }
+                            Err(e) => message = Some(format!("Describe failed: {}", e)),
+                        }
+                    }
+                }
+                KeyCode::Char('c') => {
+                    if let Some(change) = current {
+                        cherry_pick_pane(&change.change)?;
+                    }
+                }
+                _ => {}
+            }
+            
+            if refilter {
+                // Keep the cursor on the same CL if it's still shown
+                let selected = shown.get(cursor_pos).copied();
+                shown = (0..changes.len()).filter(|&idx| filter.matches(&changes[idx])).collect();
+                cursor_pos = selected.and_then(|s| shown.iter().position(|&idx| idx == s)).unwrap_or(0);
+            }
+        }
     }
     
-    let content = std::fs::read_to_string(config_path)?;
-    let cls: Vec<String> = content
-        .lines()
-        .filter(|line| !line.trim().is_empty())
-        .map(|line| line.trim().to_string())
-        .collect();
-    
-    Ok(cls)
-}
-
-fn write_tracked_cls(cls: &[String]) -> Result<()> {
-    let config_path = get_config_path()?;
-    let content = cls.join("\n");
-    std::fs::write(config_path, content)?;
     Ok(())
 }
 
-fn add_tracked_cl(cl: &str) -> Result<()> {
-    let mut cls = read_tracked_cls()?;
-    if !cls.contains(&cl.to_string()) {
-        cls.push(cl.to_string());
-        write_tracked_cls(&cls)?;
+/// Run a cherry-pick in the changes browser, showing its output until a key is pressed.
+fn cherry_pick_pane(cl: &str) -> Result<()> {
+    let mut stdout = std::io::stdout();
+    terminal::disable_raw_mode()?;
+    execute!(stdout, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0), cursor::Show)?;
+    if let Err(e) = cherry_pick_change(cl) {
+        println!("\n{} {}", "✗".bright_red(), e);
     }
-    Ok(())
+    execute!(stdout, cursor::Hide)?;
+    wait_for_key()
 }
 
-fn remove_tracked_cl(cl: &str) -> Result<()> {
-    let mut cls = read_tracked_cls()?;
-    cls.retain(|c| c != cl);
-    write_tracked_cls(&cls)?;
-    Ok(())
-}
-
-fn cmd_annotate(file_path: &str) -> Result<()> {
-    // Show loading indicator
-    print!("Loading annotate data");
-    std::io::stdout().flush()?;
-    
-    // Start spinner in a separate thread
-    let running = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true));
-    let running_clone = running.clone();
-    
-    let spinner_thread = std::thread::spawn(move || {
-        let frames = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
-        let mut idx = 0;
-        while running_clone.load(std::sync::atomic::Ordering::Relaxed) {
-            print!("\rLoading annotate data {}  ", frames[idx]);
-            std::io::stdout().flush().ok();
-            idx = (idx + 1) % frames.len();
-            std::thread::sleep(std::time::Duration::from_millis(80));
-        }
-        print!("\r{}\r", " ".repeat(50)); // Clear the line
-        std::io::stdout().flush().ok();
-    });
-    
-    // Fetch the data
-    let lines = perforce::get_annotate(file_path)?;
-    
-    // Stop spinner
-    running.store(false, std::sync::atomic::Ordering::Relaxed);
-    spinner_thread.join().ok();
-    
-    if lines.is_empty() {
-        println!("No annotate data for file: {}", file_path);
+/// Apply the changes of a submitted CL to the workspace, opening the files in a pending CL.
+/// Edits are three-way merged into the local files, with conflict markers where they don't apply cleanly.
+fn cherry_pick_change(cl: &str) -> Result<()> {
+    let files = perforce::get_submitted_files(cl)?;
+    if files.is_empty() {
+        println!("CL {} has no files.", cl);
         return Ok(());
     }
     
-    // Enter raw mode for interactive viewing
-    terminal::enable_raw_mode()?;
+    let Some(target) = select_target_cl(&format!("Cherry-pick {} file(s) of CL {} into:", files.len(), cl))? else {
+        return Ok(());
+    };
+    println!();
     
-    let mut stdout = std::io::stdout();
-    execute!(stdout, terminal::Clear(ClearType::All), cursor::Hide)?;
+    // p4 edit doesn't move a file opened in another CL, so such files are reported instead of picked
+    let opened: HashMap<String, String> = perforce::get_opened_files()?
+        .into_iter()
+        .map(|f| (f.depot_file, f.changelist))
+        .collect();
     
-    let result = annotate_viewer(&lines);
+    let mut picked = 0;
+    let mut conflicts = Vec::new();
+    let mut failed = 0;
+    for file in &files {
+        let rev: u32 = file.workrev.as_deref().and_then(|r| r.parse().ok()).unwrap_or(0);
+        let result = match opened.get(&file.depot_file) {
+            Some(opened_cl) if *opened_cl != target => {
+                Err(anyhow::anyhow!("already opened in CL {}, reopen it in CL {} first", opened_cl, target))
+            }
+            _ => cherry_pick_file(&file.depot_file, &file.action, rev, &target),
+        };
+        match result {
+            Ok(true) => {
+                println!("{} {} ({})", "✓".bright_green(), file.depot_file, file.action);
+                picked += 1;
+            }
+            Ok(false) => {
+                println!("{} {} ({}): {}", "!".bright_yellow(), file.depot_file, file.action, "conflicts".bright_yellow());
+                conflicts.push(file.depot_file.clone());
+            }
+            Err(e) => {
+                println!("{} {} ({}): {}", "✗".bright_red(), file.depot_file, file.action, e);
+                failed += 1;
+            }
+        }
+    }
     
-    // Clean up terminal state
-    execute!(stdout, cursor::Show)?;
-    terminal::disable_raw_mode()?;
+    println!();
+    if conflicts.is_empty() && failed == 0 {
+        println!("{}", format!("✓ Cherry-picked CL {} into CL {}", cl, target).bright_green());
+    } else {
+        println!("Cherry-picked {} of {} file(s) of CL {} into CL {}", picked, files.len(), cl, target);
+        if !conflicts.is_empty() {
+            println!("{}", "Conflict markers were left in these files, edit them before submitting:".bright_yellow());
+            for file in &conflicts {
+                println!("  {}", file);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Apply one file of a cherry-picked CL. Returns false if its edit left conflicts.
+fn cherry_pick_file(depot_file: &str, action: &str, rev: u32, target: &str) -> Result<bool> {
+    let local = perforce::get_local_path(depot_file)?
+        .ok_or_else(|| anyhow::anyhow!("not in client view"))?;
+    let exists = std::path::Path::new(&local).exists();
     
-    result
+    match action {
+        "delete" | "move/delete" | "purge" => {
+            if exists {
+                perforce::open_file("delete", target, &local)?;
+            }
+            Ok(true)
+        }
+        "add" | "branch" | "move/add" | "import" if !exists => {
+            let theirs = perforce::print_file(&format!("{}#{}", depot_file, rev))?;
+            if let Some(dir) = std::path::Path::new(&local).parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            write_file_atomic(&local, &theirs)?;
+            perforce::open_file("add", target, &local)?;
+            Ok(true)
+        }
+        _ => {
+            if !exists {
+                anyhow::bail!("not in the workspace, sync it first");
+            }
+            let base = if rev > 1 {
+                perforce::print_file(&format!("{}#{}", depot_file, rev - 1))?
+            } else {
+                Vec::new()
+            };
+            let theirs = perforce::print_file(&format!("{}#{}", depot_file, rev))?;
+            let yours = std::fs::read(&local).with_context(|| format!("Failed to read {}", local))?;
+            // Binary files can't be merged line by line
+            if [&base, &theirs, &yours].iter().any(|c| c.contains(&0)) {
+                anyhow::bail!("binary file, integrate it instead");
+            }
+            // Other encodings would be rewritten as UTF-8 with replacement characters
+            let (Ok(base), Ok(yours), Ok(theirs)) =
+                (std::str::from_utf8(&base), std::str::from_utf8(&yours), std::str::from_utf8(&theirs))
+            else {
+                anyhow::bail!("not UTF-8 text, integrate it instead");
+            };
+            
+            perforce::open_file("edit", target, &local)?;
+            let merge = merge::Merge::new(base, yours, theirs);
+            write_file_atomic(&local, merge.result().as_bytes())?;
+            Ok(merge.is_decided())
+        }
+    }
 }
 
 fn cmd_pack(output_path: &str) -> Result<()> {
@@ -2820,7 +5985,7 @@ fn cmd_pack(output_path: &str) -> Result<()> {
     println!("Select files to pack:");
     println!();
     
-    let selected_files = interactive_file_select(&opened, &cl_to_color, &cl_descriptions, true)?;
+    let selected_files = interactive_file_select(&opened, &cl_to_color, &cl_descriptions, true, None)?;
     
     if selected_files.is_empty() {
         println!("No files selected.");
@@ -3005,77 +6170,544 @@ fn cmd_unpack(input_path: &str) -> Result<()> {
     Ok(())
 }
 
-fn annotate_viewer(lines: &[perforce::AnnotateLine]) -> Result<()> {
-    let mut top_line = 0;
-    let mut search_query: Option<String> = None;
-    let mut search_matches: Vec<usize> = Vec::new();
-    let mut current_match_idx: Option<usize> = None;
+/// The CL that introduced an annotated line. With -a the annotation is a "first-last" range.
+fn annotate_line_cl(line: &perforce::AnnotateLine) -> Option<&str> {
+    let cl = line.cl_number.split('-').next().unwrap_or("");
+    if cl.chars().all(|c| c.is_ascii_digit()) && !cl.is_empty() {
+        Some(cl)
+    } else {
+        None
+    }
+}
+
+/// Build the lines of a describe pane for a submitted CL: header, description, affected files and
+/// diffs. With `depot_file`, that file is highlighted in the file list and only its diff is shown.
+fn build_describe_lines(cl: &str, depot_file: Option<&str>) -> Result<Vec<String>> {
+    let output = perforce::describe_change(cl)?;
+    let mut lines = Vec::new();
+    let mut in_diffs = false;
+    let mut in_file_diff = false;
+    let mut language = None;
+    let mut found_diff = false;
+    
+    for line in output.lines() {
+        if line.starts_with("Differences ...") {
+            in_diffs = true;
+            match depot_file {
+                Some(file) => lines.push(format!("{} {}", "Diff of".bright_yellow().bold(), file.bright_yellow().bold())),
+                None => lines.push(line.bright_yellow().bold().to_string()),
+            }
+            continue;
+        }
+        if !in_diffs {
+            if line.starts_with("Change ") {
+                lines.push(line.bold().to_string());
+            } else if line.starts_with("Affected files") {
+                lines.push(line.bright_yellow().bold().to_string());
+            } else if let Some(file) = line.strip_prefix("... ") {
+                let path = file.split('#').next().unwrap_or("");
+                if Some(path) == depot_file {
+                    lines.push(format!("... {}", file.bright_green().bold()));
+                } else {
+                    lines.push(line.to_string());
+                }
+            } else if let Some(desc) = line.strip_prefix('\t') {
+                lines.push(format!("  {}", desc.bright_cyan()));
+            } else {
+                lines.push(line.to_string());
+            }
+            continue;
+        }
+        
+        // Each file's diff starts with "==== //depot/path#rev (type) ===="
+        if let Some(header) = line.strip_prefix("==== ") {
+            let path = header.split('#').next().unwrap_or("");
+            in_file_diff = depot_file.is_none_or(|file| file == path);
+            found_diff |= in_file_diff;
+            language = highlight::language_for(path);
+        }
+        if in_file_diff {
+            lines.push(colorize_code_diff_line(line, language));
+        }
+    }
+    
+    if depot_file.is_some() && !found_diff {
+        // The line reached this file through an integration, or the CL only touched other files
+        lines.push(String::new());
+        lines.push(format!("  {}", format!("(no diff for this file in CL {})", cl).bright_black()));
+    }
+    
+    Ok(lines)
+}
+
+/// Find the line of `old` that corresponds to line `line` of `new`, by diffing their contents.
+/// Changed lines map to the start of the region they replaced.
+fn corresponding_line(old: &[perforce::AnnotateLine], new: &[perforce::AnnotateLine], line: usize) -> usize {
+    let old_text: Vec<&str> = old.iter().map(|l| l.line_content.as_str()).collect();
+    let new_text: Vec<&str> = new.iter().map(|l| l.line_content.as_str()).collect();
+    let last = old.len().saturating_sub(1);
+    
+    for op in similar::capture_diff_slices(similar::Algorithm::Myers, &old_text, &new_text) {
+        let (_, old_range, new_range) = op.as_tag_tuple();
+        if new_range.contains(&line) {
+            let offset = (line - new_range.start).min(old_range.len().saturating_sub(1));
+            return (old_range.start + offset).min(last);
+        }
+    }
+    line.min(last)
+}
+
+/// Lay an annotation over newer content of the file (local edits or a shelf): unchanged lines keep their
+/// attribution, edited or added lines are attributed to `username` and `date`.
+fn overlay_edits(lines: Vec<perforce::AnnotateLine>, content: &str, username: &str, date: &str) -> Vec<perforce::AnnotateLine> {
+    let present: Vec<perforce::AnnotateLine> = lines.into_iter().filter(|line| line.present).collect();
+    
+    let present_text: Vec<&str> = present.iter().map(|l| l.line_content.as_str()).collect();
+    let local_text: Vec<&str> = content.lines().collect();
+    
+    let mut out = Vec::new();
+    for op in similar::capture_diff_slices(similar::Algorithm::Myers, &present_text, &local_text) {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        if tag == similar::DiffTag::Equal {
+            out.extend(present[old_range].iter().cloned());
+        } else {
+            out.extend(local_text[new_range].iter().map(|line| perforce::AnnotateLine {
+                cl_number: "-".to_string(),
+                username: username.to_string(),
+                date: date.to_string(),
+                line_content: line.to_string(),
+                present: true,
+            }));
+        }
+    }
+    out
+}
+
+/// The active search of the annotate viewer: the regex and the lines matching it.
+#[derive(Default)]
+struct AnnotateSearch {
+    regex: Option<regex::Regex>,
+    matches: Vec<usize>,
+    current: Option<usize>, // index into matches
+}
+
+impl AnnotateSearch {
+    /// Run the search again on new lines, e.g. after reblaming, or on the lines shown after folding.
+    /// Only lines in `rows` (sorted) can match, so n/p never jump to a hidden line.
+    fn refresh(&mut self, lines: &[perforce::AnnotateLine], rows: &[usize]) {
+        if let Some(regex) = &self.regex {
+            self.matches = find_search_matches(lines, regex)
+                .into_iter()
+                .filter(|i| rows.binary_search(i).is_ok())
+                .collect();
+        }
+        self.current = if self.matches.is_empty() { None } else { Some(0) };
+    }
+}
+
+/// The indices of the annotated lines shown, in order: all of them, or only the filter's when folded.
+fn annotate_rows(lines: &[perforce::AnnotateLine], filter: Option<&str>, fold: bool) -> Vec<usize> {
+    match filter {
+        Some(f) if fold => (0..lines.len()).filter(|&i| matches_annotate_filter(&lines[i], f)).collect(),
+        _ => (0..lines.len()).collect(),
+    }
+}
+
+/// Whether an annotated line is from the user or CL of a filter.
+fn matches_annotate_filter(line: &perforce::AnnotateLine, filter: &str) -> bool {
+    line.username == filter || line.cl_number == filter || annotate_line_cl(line) == Some(filter)
+}
+
+/// Standard base64, for OSC 52 clipboard sequences.
+fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// How the annotate viewer colors the CL/user/date gutter.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum GutterColoring {
+    Plain,
+    Age,    // gradient from old to recent
+    Author, // stable color per user
+}
+
+const AGE_OLD_COLOR: (u8, u8, u8) = (70, 90, 160);
+const AGE_NEW_COLOR: (u8, u8, u8) = (255, 170, 40);
+
+const AUTHOR_COLORS: [(u8, u8, u8); 12] = [
+    (230, 100, 100), (100, 200, 100), (100, 150, 240), (230, 190, 80),
+    (200, 110, 220), (80, 200, 200), (240, 140, 60), (160, 200, 80),
+    (140, 130, 240), (230, 120, 170), (120, 180, 160), (190, 160, 120),
+];
+
+/// Days since the epoch of an annotated line's "yyyy/mm/dd" date.
+fn annotate_line_day(line: &perforce::AnnotateLine) -> Option<i64> {
+    chrono::NaiveDate::parse_from_str(&line.date, "%Y/%m/%d")
+        .ok()
+        .map(|d| d.signed_duration_since(chrono::NaiveDate::default()).num_days())
+}
+
+/// Color between AGE_OLD_COLOR (t = 0) and AGE_NEW_COLOR (t = 1).
+fn age_color(t: f64) -> (u8, u8, u8) {
+    let mix = |old: u8, new: u8| (old as f64 + (new as f64 - old as f64) * t.clamp(0.0, 1.0)).round() as u8;
+    (
+        mix(AGE_OLD_COLOR.0, AGE_NEW_COLOR.0),
+        mix(AGE_OLD_COLOR.1, AGE_NEW_COLOR.1),
+        mix(AGE_OLD_COLOR.2, AGE_NEW_COLOR.2),
+    )
+}
+
+/// A color for a user that stays the same across files and sessions.
+fn author_color(username: &str) -> (u8, u8, u8) {
+    // FNV-1a, so the color doesn't depend on the standard library's hasher
+    let hash = username.bytes().fold(2166136261u32, |h, b| (h ^ b as u32).wrapping_mul(16777619));
+    AUTHOR_COLORS[hash as usize % AUTHOR_COLORS.len()]
+}
+
+/// The gutter color of each line, or None to leave it plain.
+fn gutter_colors(lines: &[perforce::AnnotateLine], coloring: GutterColoring) -> Vec<Option<(u8, u8, u8)>> {
+    match coloring {
+        GutterColoring::Plain => vec![None; lines.len()],
+        GutterColoring::Age => {
+            let days: Vec<Option<i64>> = lines.iter().map(annotate_line_day).collect();
+            let oldest = days.iter().flatten().min().copied().unwrap_or(0);
+            let newest = days.iter().flatten().max().copied().unwrap_or(0);
+            days.iter()
+                .map(|day| {
+                    day.map(|d| {
+                        let span = (newest - oldest).max(1) as f64;
+                        age_color((d - oldest) as f64 / span)
+                    })
+                })
+                .collect()
+        }
+        GutterColoring::Author => lines
+            .iter()
+            .map(|l| if l.username == "?" { None } else { Some(author_color(&l.username)) })
+            .collect(),
+    }
+}
+
+/// Lines per author among the lines present in the annotated revision, most lines first.
+fn author_shares(lines: &[perforce::AnnotateLine]) -> Vec<(String, usize)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for line in lines {
+        if line.present && line.username != "?" {
+            *counts.entry(line.username.clone()).or_default() += 1;
+        }
+    }
+    counts.into_iter().sorted_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0))).collect()
+}
+
+/// A one-line legend for the gutter coloring.
+fn gutter_legend(lines: &[perforce::AnnotateLine], coloring: GutterColoring) -> Option<String> {
+    match coloring {
+        GutterColoring::Plain => None,
+        GutterColoring::Age => {
+            let dated: Vec<&perforce::AnnotateLine> = lines.iter().filter(|l| annotate_line_day(l).is_some()).collect();
+            let oldest = dated.iter().min_by_key(|l| annotate_line_day(l))?;
+            let newest = dated.iter().max_by_key(|l| annotate_line_day(l))?;
+            let gradient: String = (0..8)
+                .map(|i| {
+                    let (r, g, b) = age_color(i as f64 / 7.0);
+                    "█".truecolor(r, g, b).to_string()
+                })
+                .collect();
+            Some(format!("Age: {} {} {}", oldest.date, gradient, newest.date))
+        }
+        GutterColoring::Author => {
+            let authors = author_shares(lines)
+                .into_iter()
+                .take(8)
+                .map(|(user, _)| {
+                    let (r, g, b) = author_color(&user);
+                    user.truecolor(r, g, b).to_string()
+                })
+                .join(" ");
+            Some(format!("Authors: {}", authors))
+        }
+    }
+}
+
+/// Lines of the author summary pane: each author's share of the lines in the file.
+fn build_author_summary_lines(lines: &[perforce::AnnotateLine]) -> Vec<String> {
+    let shares = author_shares(lines);
+    let total: usize = shares.iter().map(|(_, count)| count).sum();
+    let name_width = shares.iter().map(|(user, _)| user.len()).max().unwrap_or(0).max(6);
+    
+    let mut out = vec![
+        format!("{:<width$}  {:>6}  {:>6}", "Author", "Lines", "Share", width = name_width).bold().to_string(),
+    ];
+    for (user, count) in &shares {
+        let share = *count as f64 / total.max(1) as f64;
+        let (r, g, b) = author_color(user);
+        let bar = "█".repeat(((share * 40.0).round() as usize).max(1));
+        out.push(format!(
+            "{:<width$}  {:>6}  {:>5.1}%  {}",
+            user,
+            count,
+            share * 100.0,
+            bar.truecolor(r, g, b),
+            width = name_width
+        ));
+    }
+    out.push(String::new());
+    out.push(format!("{} lines, {} authors", total, shares.len()).bright_black().to_string());
+    out
+}
+
+fn annotate_viewer(
+    lines: Vec<perforce::AnnotateLine>,
+    depot_file: &str,
+    revision: Option<String>,
+    options: &perforce::AnnotateOptions,
+) -> Result<()> {
+    let mut lines = lines;
+    let mut top_row = 0; // position in `rows` of the first line on screen
+    let mut cursor_line = 0;
+    let mut center_cursor = false; // scroll the cursor to the middle after a jump
+    let mut search = AnnotateSearch::default();
+    let mut coloring = GutterColoring::Plain;
+    let language = highlight::language_for(depot_file);
+    
+    // Lines from other users or CLs than the filter are dimmed, or hidden when folded
+    let mut filter: Option<String> = None;
+    let mut fold = false;
+    
+    // Reblame history: the revision shown (None for head or the local file) and the annotations we came from,
+    // with their scroll and cursor positions
+    let mut revision = revision;
+    let mut history: Vec<(Option<String>, Vec<perforce::AnnotateLine>, usize, usize)> = Vec::new();
+    let mut message: Option<String> = None;
     
     loop {
-        let (_, term_height) = terminal::size()?;
+        let (term_width, term_height) = terminal::size()?;
         let visible_lines = (term_height as usize).saturating_sub(2); // Leave space for status bar
         
+        // The lines shown, in order
+        let rows = annotate_rows(&lines, filter.as_deref(), fold);
+        
+        // Move the cursor to a shown line and keep it on screen
+        let cursor_row = rows.partition_point(|&i| i < cursor_line).min(rows.len().saturating_sub(1));
+        cursor_line = rows.get(cursor_row).copied().unwrap_or(0);
+        if center_cursor {
+            top_row = cursor_row.saturating_sub(visible_lines / 2);
+            center_cursor = false;
+        }
+        top_row = scroll_to_row(top_row, cursor_row, visible_lines, rows.len());
+        
         // Render the visible portion
-        render_annotate_page(lines, top_line, visible_lines, &search_query, &search_matches, current_match_idx)?;
+        render_annotate_page(&AnnotatePage {
+            lines: &lines,
+            rows: &rows,
+            top_row,
+            cursor_line,
+            visible_lines,
+            search: &search,
+            gutter_colors: &gutter_colors(&lines, coloring),
+            language,
+            filter: filter.as_deref(),
+        })?;
+        
+        // The bottom line shows a one-off message or the annotated revision, then the gutter legend
+        let bottom = message.take().or_else(|| {
+            revision.as_ref().map(|rev| {
+                let back = if history.is_empty() { "" } else { " (Backspace: back to newer)" };
+                format!("Annotating {}{}{}", depot_file, rev, back)
+            })
+        });
+        let bottom = [bottom.map(|text| text.bright_yellow().to_string()), gutter_legend(&lines, coloring)]
+            .into_iter()
+            .flatten()
+            .join("  ");
+        let mut stdout = std::io::stdout();
+        execute!(stdout, cursor::MoveTo(0, term_height.saturating_sub(1)), terminal::Clear(ClearType::CurrentLine))?;
+        write!(stdout, "{}", truncate_colored_to_width(&bottom, term_width as usize))?;
+        stdout.flush()?;
         
         // Handle keyboard input
+        let last_row = rows.len().saturating_sub(1);
+        let row_line = |row: usize| rows.get(row.min(last_row)).copied().unwrap_or(0);
         if let Event::Key(KeyEvent { code, .. }) = event::read()? {
             match code {
                 KeyCode::Char('q') | KeyCode::Esc => break,
                 KeyCode::PageDown | KeyCode::Char(' ') => {
-                    top_line = (top_line + visible_lines).min(lines.len().saturating_sub(1));
+                    top_row += visible_lines;
+                    cursor_line = row_line(cursor_row + visible_lines);
                 }
                 KeyCode::PageUp => {
-                    top_line = top_line.saturating_sub(visible_lines);
+                    top_row = top_row.saturating_sub(visible_lines);
+                    cursor_line = row_line(cursor_row.saturating_sub(visible_lines));
                 }
                 KeyCode::Down | KeyCode::Char('j') => {
-                    top_line = (top_line + 1).min(lines.len().saturating_sub(visible_lines));
+                    cursor_line = row_line(cursor_row + 1);
                 }
                 KeyCode::Up | KeyCode::Char('k') => {
-                    top_line = top_line.saturating_sub(1);
+                    cursor_line = row_line(cursor_row.saturating_sub(1));
                 }
                 KeyCode::Home | KeyCode::Char('g') => {
-                    top_line = 0;
+                    cursor_line = row_line(0);
                 }
                 KeyCode::End | KeyCode::Char('G') => {
-                    top_line = lines.len().saturating_sub(visible_lines);
+                    cursor_line = row_line(last_row);
+                }
+                KeyCode::Char(':') => {
+                    // Go to a line of the file. With -a the annotation also lists deleted lines, so skip those.
+                    if let Some(input) = prompt_input(":")? {
+                        let target = input.trim().parse::<usize>().ok().and_then(|n| {
+                            lines
+                                .iter()
+                                .enumerate()
+                                .filter(|(_, line)| line.present)
+                                .nth(n.saturating_sub(1))
+                                .map(|(i, _)| i)
+                        });
+                        match target {
+                            Some(line) => {
+                                cursor_line = line;
+                                center_cursor = true;
+                            }
+                            None => message = Some(format!("No line {}", input.trim())),
+                        }
+                    }
+                }
+                KeyCode::Char('f') => {
+                    // Filter by user or CL; an empty filter clears it
+                    match prompt_input("Filter by user or CL (empty to clear): ")? {
+                        Some(f) if lines.iter().any(|l| matches_annotate_filter(l, f.trim())) => {
+                            filter = Some(f.trim().to_string());
+                            center_cursor = true;
+                        }
+                        Some(f) => message = Some(format!("No lines from user or CL '{}'", f.trim())),
+                        None => {
+                            filter = None;
+                            fold = false;
+                        }
+                    }
+                    search.refresh(&lines, &annotate_rows(&lines, filter.as_deref(), fold));
+                }
+                KeyCode::Char('z') => {
+                    if filter.is_some() {
+                        fold = !fold;
+                        center_cursor = true;
+                        search.refresh(&lines, &annotate_rows(&lines, filter.as_deref(), fold));
+                    } else {
+                        message = Some("Set a filter with f first".to_string());
+                    }
+                }
+                KeyCode::Char('y') => {
+                    // OSC 52 asks the terminal to set the system clipboard, which also works over ssh
+                    if let Some(cl) = lines.get(cursor_line).and_then(annotate_line_cl) {
+                        write!(stdout, "\x1b]52;c;{}\x07", base64_encode(cl.as_bytes()))?;
+                        stdout.flush()?;
+                        message = Some(format!("Copied CL {} to the clipboard", cl));
+                    }
+                }
+                KeyCode::Char('b') => {
+                    // Reblame: annotate the file as it was just before the CL that introduced this line
+                    let Some(cl) = lines.get(cursor_line).and_then(annotate_line_cl).and_then(|cl| cl.parse::<u64>().ok()) else {
+                        continue;
+                    };
+                    let parent_rev = format!("@{}", cl.saturating_sub(1));
+                    
+                    execute!(stdout, cursor::MoveTo(0, term_height.saturating_sub(1)), terminal::Clear(ClearType::CurrentLine))?;
+                    write!(stdout, "Loading annotate data for {}{}...", depot_file, parent_rev)?;
+                    stdout.flush()?;
+                    
+                    match perforce::get_annotate(&format!("{}{}", depot_file, parent_rev), options, true) {
+                        Ok(parent_lines) if !parent_lines.is_empty() => {
+                            let parent_cursor = corresponding_line(&parent_lines, &lines, cursor_line);
+                            let previous = std::mem::replace(&mut lines, parent_lines);
+                            history.push((revision.replace(parent_rev), previous, top_row, cursor_line));
+                            cursor_line = parent_cursor;
+                            center_cursor = true;
+                        }
+                        _ => {
+                            message = Some(format!("No earlier revision: the file did not exist before CL {}", cl));
+                            continue;
+                        }
+                    }
+                    
+                    // Search results refer to the old lines
+                    search.refresh(&lines, &annotate_rows(&lines, filter.as_deref(), fold));
+                }
+                KeyCode::Backspace => {
+                    // Back to the annotation we reblamed from
+                    if let Some((rev, previous, top, cursor)) = history.pop() {
+                        revision = rev;
+                        lines = previous;
+                        top_row = top;
+                        cursor_line = cursor;
+                        search.refresh(&lines, &annotate_rows(&lines, filter.as_deref(), fold));
+                    }
+                }
+                KeyCode::Char('c') => {
+                    coloring = match coloring {
+                        GutterColoring::Plain => GutterColoring::Age,
+                        GutterColoring::Age => GutterColoring::Author,
+                        GutterColoring::Author => GutterColoring::Plain,
+                    };
+                }
+                KeyCode::Char('a') => {
+                    let summary = build_author_summary_lines(&lines);
+                    pager_viewer(&format!("Authors of {}", depot_file), &summary, &[])?;
+                    execute!(stdout, terminal::Clear(ClearType::All))?;
+                }
+                KeyCode::Enter => {
+                    // Drill into the CL that introduced the line under the cursor
+                    if let Some(cl) = lines.get(cursor_line).and_then(annotate_line_cl) {
+                        let describe_lines = build_describe_lines(cl, Some(depot_file))?;
+                        pager_viewer(&format!("CL {}", cl), &describe_lines, &[])?;
+                        // The pager drew over the whole screen
+                        execute!(stdout, terminal::Clear(ClearType::All))?;
+                    }
                 }
                 KeyCode::Char('/') => {
-                    // Enter search mode
-                    if let Some(query) = prompt_search()? {
-                        search_query = Some(query.to_lowercase());
-                        search_matches = find_search_matches(lines, &search_query.as_ref().unwrap());
-                        current_match_idx = if !search_matches.is_empty() {
-                            Some(0)
-                        } else {
-                            None
-                        };
-                        // Jump to first match
-                        if let Some(0) = current_match_idx {
-                            if !search_matches.is_empty() {
-                                top_line = search_matches[0].saturating_sub(visible_lines / 2);
+                    // Regex search, case-insensitive unless the pattern has uppercase letters
+                    if let Some(query) = prompt_input("/")? {
+                        let case_insensitive = !query.chars().any(|c| c.is_uppercase());
+                        match regex::RegexBuilder::new(&query).case_insensitive(case_insensitive).build() {
+                            Ok(regex) => {
+                                search.regex = Some(regex);
+                                search.refresh(&lines, &rows);
+                                // Jump to first match
+                                if let Some(&first) = search.matches.first() {
+                                    cursor_line = first;
+                                    center_cursor = true;
+                                }
                             }
+                            Err(e) => message = Some(format!("Invalid regex: {}", e)),
                         }
                     }
                 }
                 KeyCode::Char('n') => {
                     // Next match
-                    if let Some(idx) = current_match_idx {
-                        if !search_matches.is_empty() {
-                            let next_idx = (idx + 1) % search_matches.len();
-                            current_match_idx = Some(next_idx);
-                            top_line = search_matches[next_idx].saturating_sub(visible_lines / 2);
-                        }
+                    if let Some(idx) = search.current {
+                        let next_idx = (idx + 1) % search.matches.len();
+                        search.current = Some(next_idx);
+                        cursor_line = search.matches[next_idx];
+                        center_cursor = true;
                     }
                 }
                 KeyCode::Char('p') | KeyCode::Char('N') => {
                     // Previous match
-                    if let Some(idx) = current_match_idx {
-                        if !search_matches.is_empty() {
-                            let prev_idx = if idx == 0 { search_matches.len() - 1 } else { idx - 1 };
-                            current_match_idx = Some(prev_idx);
-                            top_line = search_matches[prev_idx].saturating_sub(visible_lines / 2);
-                        }
+                    if let Some(idx) = search.current {
+                        let prev_idx = if idx == 0 { search.matches.len() - 1 } else { idx - 1 };
+                        search.current = Some(prev_idx);
+                        cursor_line = search.matches[prev_idx];
+                        center_cursor = true;
                     }
                 }
                 _ => {}
@@ -3086,20 +6718,24 @@ fn annotate_viewer(lines: &[perforce::AnnotateLine]) -> Result<()> {
     Ok(())
 }
 
-fn render_annotate_page(
-    lines: &[perforce::AnnotateLine],
-    top_line: usize,
+/// What `render_annotate_page` draws.
+struct AnnotatePage<'a> {
+    lines: &'a [perforce::AnnotateLine],
+    rows: &'a [usize], // indices of the lines shown, in order: all of them unless folded by a filter
+    top_row: usize,
+    cursor_line: usize,
     visible_lines: usize,
-    search_query: &Option<String>,
-    search_matches: &[usize],
-    current_match_idx: Option<usize>,
-) -> Result<()> {
-    let mut stdout = std::io::stdout();
+    search: &'a AnnotateSearch,
+    gutter_colors: &'a [Option<(u8, u8, u8)>],
+    language: Option<&'a highlight::Language>,
+    filter: Option<&'a str>, // lines not matching are dimmed
+}
+
+fn render_annotate_page(page: &AnnotatePage) -> Result<()> {
+    let AnnotatePage { lines, rows, top_row, cursor_line, visible_lines, search, gutter_colors, language, filter } = *page;
     let (term_width, _) = terminal::size()?;
     
-    execute!(stdout, cursor::MoveTo(0, 0))?;
-    
-    let end_line = (top_line + visible_lines).min(lines.len());
+    let end_row = (top_row + visible_lines).min(rows.len());
     
     // Find the max width for each column to align properly
     let max_cl_width = lines.iter()
@@ -3113,30 +6749,27 @@ fn render_annotate_page(
         .unwrap_or(10)
         .max(10);
     
-    for i in top_line..end_line {
+    let shown = rows[top_row.min(end_row)..end_row].iter().map(|&i| {
         let line = &lines[i];
         
-        // Clear the entire line first
-        execute!(stdout, terminal::Clear(ClearType::CurrentLine))?;
-        
         // Check if this line is a search match
-        let is_current_match = current_match_idx
-            .and_then(|idx| search_matches.get(idx))
+        let is_current_match = search.current
+            .and_then(|idx| search.matches.get(idx))
             .map(|&match_line| match_line == i)
             .unwrap_or(false);
         
-        let is_match = search_matches.contains(&i);
+        let is_match = search.matches.contains(&i);
         
         // Format the line with proper column alignment
-        let formatted = format!(
-            "{:>width_cl$} {:width_user$} {} {}",
+        let gutter = format!(
+            "{:>width_cl$} {:width_user$} {}",
             line.cl_number,
             line.username,
             line.date,
-            line.line_content,
             width_cl = max_cl_width,
             width_user = max_user_width,
         );
+        let formatted = format!("{} {}", gutter, line.line_content);
         
         // Truncate to terminal width if necessary to prevent wrapping
         // Use char-based truncation to handle Unicode properly
@@ -3150,78 +6783,112 @@ fn render_annotate_page(
             formatted
         };
         
-        // Highlight current match or regular match
-        if is_current_match {
-            write!(stdout, "{}\r\n", truncated.black().on_yellow())?;
+        // Highlight the cursor line, then current match or regular match, then dim filtered-out lines
+        if i == cursor_line {
+            truncated.reversed().to_string()
+        } else if is_current_match {
+            truncated.black().on_yellow().to_string()
         } else if is_match {
-            write!(stdout, "{}\r\n", truncated.on_bright_black())?;
+            truncated.on_bright_black().to_string()
+        } else if filter.is_some_and(|f| !matches_annotate_filter(line, f)) {
+            truncated.bright_black().to_string()
+        } else if gutter_colors.get(i).is_some_and(|c| c.is_some()) || language.is_some() {
+            let gutter = match gutter_colors.get(i) {
+                Some(Some((r, g, b))) => gutter.truecolor(*r, *g, *b).to_string(),
+                _ => gutter,
+            };
+            let content = match language {
+                Some(lang) => highlight::highlight(&line.line_content, lang),
+                None => line.line_content.clone(),
+            };
+            truncate_colored_to_width(&format!("{} {}", gutter, content), term_width as usize)
         } else {
-            write!(stdout, "{}\r\n", truncated)?;
+            truncated
         }
-    }
-    
-    // Clear remaining lines
-    for _ in end_line..top_line + visible_lines {
-        execute!(stdout, terminal::Clear(ClearType::CurrentLine))?;
-        write!(stdout, "\r\n")?;
-    }
+    });
+    draw_list_rows(shown, visible_lines)?;
     
     // Status bar
-    execute!(stdout, cursor::MoveTo(0, visible_lines as u16), terminal::Clear(ClearType::CurrentLine))?;
-    let status = if let Some(ref query) = search_query {
-        if let Some(idx) = current_match_idx {
-            format!(
-                "Lines {}-{}/{} | Search: '{}' ({}/{} matches) | q:quit /:search n:next p:prev",
-                top_line + 1,
-                end_line,
-                lines.len(),
-                query,
-                idx + 1,
-                search_matches.len()
-            )
-        } else {
-            format!(
-                "Lines {}-{}/{} | Search: '{}' (no matches) | q:quit /:search",
-                top_line + 1,
-                end_line,
-                lines.len(),
-                query
-            )
+    let first = rows.get(top_row).map(|i| i + 1).unwrap_or(0);
+    let last = if end_row > top_row { rows[end_row - 1] + 1 } else { 0 };
+    let mut status = format!("Lines {}-{}/{}", first, last, lines.len());
+    if let Some(regex) = &search.regex {
+        match search.current {
+            Some(idx) => status.push_str(&format!(" | Search: /{}/ ({}/{} matches)", regex.as_str(), idx + 1, search.matches.len())),
+            None => status.push_str(&format!(" | Search: /{}/ (no matches)", regex.as_str())),
         }
-    } else {
-        format!(
-            "Lines {}-{}/{} | q:quit /:search ↑↓:scroll PgUp/PgDn:page",
-            top_line + 1,
-            end_line,
-            lines.len()
-        )
-    };
+    }
+    if let Some(f) = filter {
+        let folded = if rows.len() < lines.len() { ", folded" } else { "" };
+        status.push_str(&format!(" | Filter: {}{}", f, folded));
+    }
+    status.push_str(" | q:quit Enter:describe b:blame parent c:colors a:authors /:search n:next p:prev :line f:filter z:fold y:copy CL");
+    draw_status_bar(visible_lines as u16, &status)?;
     
-    // Pad or truncate status to fill the terminal width
-    let status_display = if status.chars().count() > term_width as usize {
-        let mut truncated = status.chars()
-            .take(term_width as usize - 1)
-            .collect::<String>();
-        truncated.push('…');
-        truncated
+    std::io::stdout().flush()?;
+    Ok(())
+}
+
+/// The first row on screen that keeps `cursor_row` in view, moving as little as possible from `top_row`
+/// and never scrolling past the last row.
+fn scroll_to_row(top_row: usize, cursor_row: usize, visible_lines: usize, total_rows: usize) -> usize {
+    let top_row = top_row.min(total_rows.saturating_sub(visible_lines));
+    if cursor_row < top_row {
+        cursor_row
+    } else if cursor_row >= top_row + visible_lines {
+        cursor_row + 1 - visible_lines
     } else {
-        // Pad with spaces to fill terminal width
-        let padding = term_width as usize - status.chars().count();
-        format!("{}{}", status, " ".repeat(padding))
-    };
-    write!(stdout, "{}", status_display.black().on_white())?;
-    
-    stdout.flush()?;
+        top_row
+    }
+}
+
+/// Draw the rows of a full-screen list from the top of the screen and blank the rest of its
+/// `visible_lines`. Each line is cleared just before it is written, so redrawing doesn't flicker.
+/// Rows must already fit the terminal width.
+fn draw_list_rows(rows: impl IntoIterator<Item = String>, visible_lines: usize) -> Result<()> {
+    let mut stdout = std::io::stdout();
+    execute!(stdout, cursor::MoveTo(0, 0))?;
+    let mut drawn = 0;
+    for row in rows.into_iter().take(visible_lines) {
+        execute!(stdout, terminal::Clear(ClearType::CurrentLine))?;
+        write!(stdout, "{}\r\n", row)?;
+        drawn += 1;
+    }
+    for _ in drawn..visible_lines {
+        execute!(stdout, terminal::Clear(ClearType::CurrentLine))?;
+        write!(stdout, "\r\n")?;
+    }
+    Ok(())
+}
+
+/// Draw the black on white status bar of a full-screen view on screen row `row`.
+fn draw_status_bar(row: u16, status: &str) -> Result<()> {
+    let (term_width, _) = terminal::size()?;
+    let mut stdout = std::io::stdout();
+    execute!(stdout, cursor::MoveTo(0, row))?;
+    write!(stdout, "{}", fit_to_width(status, term_width as usize).black().on_white())?;
+    Ok(())
+}
+
+/// Show a one-off message on the bottom line of a full-screen view, or blank the line.
+fn draw_message_line(message: Option<&str>) -> Result<()> {
+    let (term_width, term_height) = terminal::size()?;
+    let mut stdout = std::io::stdout();
+    execute!(stdout, cursor::MoveTo(0, term_height.saturating_sub(1)), terminal::Clear(ClearType::CurrentLine))?;
+    if let Some(text) = message {
+        write!(stdout, "{}", truncate_to_width(text, term_width as usize).bright_yellow())?;
+    }
     Ok(())
 }
 
-fn prompt_search() -> Result<Option<String>> {
+/// Read a line of input on the bottom row of the screen after `prefix`. Returns None on Esc or empty input.
+fn prompt_input(prefix: &str) -> Result<Option<String>> {
     let mut stdout = std::io::stdout();
     let (_, term_height) = terminal::size()?;
     
     // Show prompt at the bottom
     execute!(stdout, cursor::MoveTo(0, term_height - 1), terminal::Clear(ClearType::CurrentLine))?;
-    write!(stdout, "/")?;
+    write!(stdout, "{}", prefix)?;
     stdout.flush()?;
     
     execute!(stdout, cursor::Show)?;
@@ -3241,7 +6908,7 @@ fn prompt_search() -> Result<Option<String>> {
                 KeyCode::Backspace => {
                     query.pop();
                     execute!(stdout, cursor::MoveTo(0, term_height - 1), terminal::Clear(ClearType::CurrentLine))?;
-                    write!(stdout, "/{}", query)?;
+                    write!(stdout, "{}{}", prefix, query)?;
                     stdout.flush()?;
                 }
                 KeyCode::Char(c) => {
@@ -3255,15 +6922,15 @@ fn prompt_search() -> Result<Option<String>> {
     }
 }
 
-fn find_search_matches(lines: &[perforce::AnnotateLine], query: &str) -> Vec<usize> {
+fn find_search_matches(lines: &[perforce::AnnotateLine], regex: &regex::Regex) -> Vec<usize> {
     lines
         .iter()
         .enumerate()
         .filter(|(_, line)| {
-            line.cl_number.to_lowercase().contains(query)
-                || line.username.to_lowercase().contains(query)
-                || line.date.to_lowercase().contains(query)
-                || line.line_content.to_lowercase().contains(query)
+            regex.is_match(&line.cl_number)
+                || regex.is_match(&line.username)
+                || regex.is_match(&line.date)
+                || regex.is_match(&line.line_content)
         })
         .map(|(i, _)| i)
         .collect()
@@ -0,0 +1,247 @@
+use similar::{capture_diff_slices, Algorithm, DiffTag};
+use std::ops::Range;
+
+/// Which sides changed a region of the base file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HunkKind {
+    Yours,    // only your side changed it
+    Theirs,   // only their side changed it
+    Same,     // both sides made the same change
+    Conflict, // both sides changed it differently
+}
+
+/// What to put in the merge result for a hunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Choice {
+    Yours,
+    Theirs,
+    Both, // yours followed by theirs
+}
+
+/// A region where yours or theirs differ from base, as line ranges into each side.
+#[derive(Debug, Clone)]
+pub struct Hunk {
+    pub kind: HunkKind,
+    pub base: Range<usize>,
+    pub yours: Range<usize>,
+    pub theirs: Range<usize>,
+    pub choice: Option<Choice>, // None until a conflict is decided
+}
+
+/// A three-way merge of one file. Lines keep their line endings.
+pub struct Merge {
+    pub base: Vec<String>,
+    pub yours: Vec<String>,
+    pub theirs: Vec<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+fn split_lines(s: &str) -> Vec<String> {
+    s.split_inclusive('\n').map(|l| l.to_string()).collect()
+}
+
+/// Ranges of base replaced in `side`, as (base range, side range), with neighbouring ops joined.
+fn changed_ranges(base: &[String], side: &[String]) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut ranges: Vec<(Range<usize>, Range<usize>)> = Vec::new();
+    for op in capture_diff_slices(Algorithm::Myers, base, side) {
+        let (tag, old, new) = op.as_tag_tuple();
+        if tag == DiffTag::Equal {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some((b, s)) if b.end == old.start && s.end == new.start => {
+                b.end = old.end;
+                s.end = new.end;
+            }
+            _ => ranges.push((old, new)),
+        }
+    }
+    ranges
+}
+
+impl Merge {
+    pub fn new(base: &str, yours: &str, theirs: &str) -> Merge {
+        let base = split_lines(base);
+        let yours = split_lines(yours);
+        let theirs = split_lines(theirs);
+
+        let yours_changes = changed_ranges(&base, &yours);
+        let theirs_changes = changed_ranges(&base, &theirs);
+
+        // Sweep both change lists in base order, joining changes that overlap or touch into one hunk.
+        // Outside its changes a side matches base, so its range in a hunk follows from the
+        // line count difference (delta) of its earlier changes.
+        let mut hunks = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        let (mut yours_delta, mut theirs_delta) = (0isize, 0isize);
+
+        while i < yours_changes.len() || j < theirs_changes.len() {
+            let first = match (yours_changes.get(i), theirs_changes.get(j)) {
+                (Some(y), Some(t)) => y.0.start.min(t.0.start),
+                (Some(y), None) => y.0.start,
+                (None, Some(t)) => t.0.start,
+                (None, None) => break,
+            };
+            let mut base_range = first..first;
+            let (yours_before, theirs_before) = (yours_delta, theirs_delta);
+            let (mut has_yours, mut has_theirs) = (false, false);
+
+            loop {
+                if i < yours_changes.len() && yours_changes[i].0.start <= base_range.end {
+                    let (b, s) = &yours_changes[i];
+                    base_range.end = base_range.end.max(b.end);
+                    yours_delta += s.len() as isize - b.len() as isize;
+                    has_yours = true;
+                    i += 1;
+                } else if j < theirs_changes.len() && theirs_changes[j].0.start <= base_range.end {
+                    let (b, s) = &theirs_changes[j];
+                    base_range.end = base_range.end.max(b.end);
+                    theirs_delta += s.len() as isize - b.len() as isize;
+                    has_theirs = true;
+                    j += 1;
+                } else {
+                    break;
+                }
+            }
+
+            let side_range = |before: isize, after: isize| {
+                (base_range.start as isize + before) as usize..(base_range.end as isize + after) as usize
+            };
+            let yours_range = side_range(yours_before, yours_delta);
+            let theirs_range = side_range(theirs_before, theirs_delta);
+
+            let kind = match (has_yours, has_theirs) {
+                (true, false) => HunkKind::Yours,
+                (false, true) => HunkKind::Theirs,
+                _ if yours[yours_range.clone()] == theirs[theirs_range.clone()] => HunkKind::Same,
+                _ => HunkKind::Conflict,
+            };
+            let choice = match kind {
+                HunkKind::Yours | HunkKind::Same => Some(Choice::Yours),
+                HunkKind::Theirs => Some(Choice::Theirs),
+                HunkKind::Conflict => None,
+            };
+
+            hunks.push(Hunk { kind, base: base_range, yours: yours_range, theirs: theirs_range, choice });
+        }
+
+        Merge { base, yours, theirs, hunks }
+    }
+
+    /// Whether every hunk has a choice, so the result has no conflict markers.
+    pub fn is_decided(&self) -> bool {
+        self.hunks.iter().all(|h| h.choice.is_some())
+    }
+
+    /// The merged file. Undecided hunks are written with diff3-style conflict markers.
+    pub fn result(&self) -> String {
+        let mut out = String::new();
+        let mut pos = 0;
+
+        // Markers must start on their own line, even if a side ends without a newline
+        fn push_lines(out: &mut String, lines: &[String]) {
+            for line in lines {
+                out.push_str(line);
+            }
+        }
+        fn push_marker(out: &mut String, marker: &str) {
+            if !out.is_empty() && !out.ends_with('\n') {
+                out.push('\n');
+            }
+            out.push_str(marker);
+            out.push('\n');
+        }
+
+        for hunk in &self.hunks {
+            push_lines(&mut out, &self.base[pos..hunk.base.start]);
+            let yours = &self.yours[hunk.yours.clone()];
+            let theirs = &self.theirs[hunk.theirs.clone()];
+            match hunk.choice {
+                Some(Choice::Yours) => push_lines(&mut out, yours),
+                Some(Choice::Theirs) => push_lines(&mut out, theirs),
+                Some(Choice::Both) => {
+                    push_lines(&mut out, yours);
+                    if !out.is_empty() && !out.ends_with('\n') {
+                        out.push('\n');
+                    }
+                    push_lines(&mut out, theirs);
+                }
+                None => {
+                    push_marker(&mut out, "<<<<<<< yours");
+                    push_lines(&mut out, yours);
+                    push_marker(&mut out, "||||||| base");
+                    push_lines(&mut out, &self.base[hunk.base.clone()]);
+                    push_marker(&mut out, "=======");
+                    push_lines(&mut out, theirs);
+                    push_marker(&mut out, ">>>>>>> theirs");
+                }
+            }
+            pos = hunk.base.end;
+        }
+        push_lines(&mut out, &self.base[pos..]);
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_to_different_lines_merge_cleanly() {
+        let merge = Merge::new("a\nb\nc\nd\ne\n", "x\na\nb\nc\nd\ne\n", "a\nb\nc\nD\ne\n");
+        let kinds: Vec<HunkKind> = merge.hunks.iter().map(|h| h.kind).collect();
+        assert_eq!(kinds, [HunkKind::Yours, HunkKind::Theirs]);
+        // Your insertion shifts your range of their hunk by one line
+        assert_eq!(merge.hunks[1].base, 3..4);
+        assert_eq!(merge.hunks[1].yours, 4..5);
+        assert_eq!(merge.hunks[1].theirs, 3..4);
+        assert!(merge.is_decided());
+        assert_eq!(merge.result(), "x\na\nb\nc\nD\ne\n");
+    }
+
+    #[test]
+    fn identical_changes_are_same() {
+        let merge = Merge::new("a\nb\nc\n", "a\nB\nc\n", "a\nB\nc\n");
+        assert_eq!(merge.hunks.len(), 1);
+        assert_eq!(merge.hunks[0].kind, HunkKind::Same);
+        assert_eq!(merge.result(), "a\nB\nc\n");
+    }
+
+    #[test]
+    fn touching_changes_are_swept_into_one_conflict() {
+        let merge = Merge::new("a\nb\nc\nd\n", "a\nB\nc\nd\n", "a\nb\nC\nd\n");
+        assert_eq!(merge.hunks.len(), 1);
+        let hunk = &merge.hunks[0];
+        assert_eq!(hunk.kind, HunkKind::Conflict);
+        assert_eq!((hunk.base.clone(), hunk.yours.clone(), hunk.theirs.clone()), (1..3, 1..3, 1..3));
+        assert!(!merge.is_decided());
+        assert_eq!(
+            merge.result(),
+            "a\n<<<<<<< yours\nB\nc\n||||||| base\nb\nc\n=======\nb\nC\n>>>>>>> theirs\nd\n"
+        );
+    }
+
+    #[test]
+    fn chosen_conflicts_are_written_without_markers() {
+        let mut merge = Merge::new("a\nb\n", "a\nY\n", "a\nT\n");
+        merge.hunks[0].choice = Some(Choice::Theirs);
+        assert_eq!(merge.result(), "a\nT\n");
+        merge.hunks[0].choice = Some(Choice::Both);
+        assert_eq!(merge.result(), "a\nY\nT\n");
+    }
+
+    #[test]
+    fn markers_start_on_their_own_line_without_a_final_newline() {
+        let merge = Merge::new("a\nb", "a\nY", "a\nT");
+        assert_eq!(merge.result(), "a\n<<<<<<< yours\nY\n||||||| base\nb\n=======\nT\n>>>>>>> theirs\n");
+    }
+
+    #[test]
+    fn both_keeps_lines_apart_without_a_final_newline() {
+        let mut merge = Merge::new("a\n", "a\nY", "a\nT");
+        merge.hunks[0].choice = Some(Choice::Both);
+        assert_eq!(merge.result(), "a\nY\nT");
+    }
+}
@@ -102,7 +102,8 @@ pub fn get_change_description(cl_number: &str) -> Result<Option<String>> {
         }
         if in_description {
             if line.starts_with('\t') || line.starts_with("    ") {
-                description.push_str(line.trim());
+                // Only strip the spec's indentation, so indented description lines survive an edit
+                description.push_str(line.strip_prefix('\t').unwrap_or(line.trim()).trim_end());
                 description.push('\n');
             } else {
                 break;
@@ -113,8 +114,38 @@ pub fn get_change_description(cl_number: &str) -> Result<Option<String>> {
     Ok(Some(description.trim().to_string()))
 }
 
-/// Create a new changelist. Returns the CL number.
-pub fn create_changelist() -> Result<String> {
+/// Replace the Description field of a change spec.
+fn replace_spec_description(spec: &str, description: &str) -> String {
+    // Description lines are tab-indented and end at the next field (or end of spec)
+    let mut modified = String::new();
+    let mut in_description = false;
+    for line in spec.lines() {
+        if in_description {
+            if line.starts_with('\t') || line.trim().is_empty() {
+                continue;
+            }
+            in_description = false;
+        }
+        if line.starts_with("Description:") {
+            in_description = true;
+            modified.push_str("Description:\n");
+            for desc_line in description.lines() {
+                modified.push('\t');
+                modified.push_str(desc_line);
+                modified.push('\n');
+            }
+            modified.push('\n');
+        } else {
+            modified.push_str(line);
+            modified.push('\n');
+        }
+    }
+    modified
+}
+
+/// Create a new changelist with the given description, moving `files` (depot paths of files
+/// opened in the default changelist) into it. Returns the CL number.
+pub fn create_changelist(description: &str, files: &[String]) -> Result<String> {
     let output = Command::new("p4")
         .arg("change")
         .arg("-o")
@@ -129,16 +160,35 @@ pub fn create_changelist() -> Result<String> {
     let template = String::from_utf8_lossy(&output.stdout);
     let mut modified = String::new();
     
-    for line in template.lines() {
+    // The template lists every file in the default changelist: only keep the ones asked for
+    let mut in_files = false;
+    for line in replace_spec_description(&template, description).lines() {
+        if in_files {
+            if line.starts_with('\t') || line.trim().is_empty() {
+                continue;
+            }
+            in_files = false;
+        }
         if line.starts_with("Change:") {
             modified.push_str("Change:\tnew\n");
-        } else if line.starts_with("Description:") {
-            modified.push_str("Description:\n\t<enter description here>\n");
+        } else if line.starts_with("Files:") {
+            in_files = true;
         } else {
             modified.push_str(line);
             modified.push('\n');
         }
     }
+    if !files.is_empty() {
+        if !modified.ends_with("\n\n") {
+            modified.push('\n');
+        }
+        modified.push_str("Files:\n");
+        for file in files {
+            modified.push('\t');
+            modified.push_str(file);
+            modified.push('\n');
+        }
+    }
     
     let mut child = Command::new("p4")
         .arg("change")
@@ -168,12 +218,66 @@ pub fn create_changelist() -> Result<String> {
     }
 }
 
+/// Replace the description of a pending changelist, leaving its file list and other fields untouched.
+pub fn set_change_description(cl_number: &str, description: &str) -> Result<()> {
+    let spec = run("p4", &["change", "-o", cl_number])?;
+    let modified = replace_spec_description(&spec, description);
+    
+    let mut child = Command::new("p4")
+        .arg("change")
+        .arg("-i")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to spawn p4 change -i")?;
+    
+    child.stdin.as_mut().unwrap().write_all(modified.as_bytes())?;
+    
+    let output = child.wait_with_output()?;
+    
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("Failed to update changelist {}: {}", cl_number, err.trim());
+    }
+    
+    Ok(())
+}
+
 /// Get shelved files from a changelist
 pub fn get_shelved_files(cl_number: &str) -> Result<Vec<OpenedFile>> {
-    let stdout = run("p4", &["-ztag", "describe", "-S", "-s", cl_number])?;
+    Ok(get_described_files(cl_number, true)?.0)
+}
+
+/// Get the shelved files of a changelist and when it was last shelved ("YYYY/MM/DD hh:mm:ss", local
+/// time), None if nothing is shelved. p4 keeps one date per pending change, which shelving updates.
+pub fn get_shelf(cl_number: &str) -> Result<(Vec<OpenedFile>, Option<String>)> {
+    let (files, time) = get_described_files(cl_number, true)?;
+    let shelved_at = time
+        .filter(|_| !files.is_empty())
+        .and_then(|secs| format_p4_time(&secs, "%Y/%m/%d %H:%M:%S"));
+    Ok((files, shelved_at))
+}
+
+/// Get the files of a submitted changelist, with the revisions it created.
+pub fn get_submitted_files(cl_number: &str) -> Result<Vec<OpenedFile>> {
+    Ok(get_described_files(cl_number, false)?.0)
+}
+
+/// Files of a described changelist, and its date in seconds since the epoch.
+fn get_described_files(cl_number: &str, shelved: bool) -> Result<(Vec<OpenedFile>, Option<String>)> {
+    let args: &[&str] = if shelved {
+        &["-ztag", "describe", "-S", "-s", cl_number]
+    } else {
+        &["-ztag", "describe", "-s", cl_number]
+    };
+    let stdout = run("p4", args)?;
     let line_re = Regex::new(r"^\.\.\.\s+(\w+?)(\d*)\s+(.+)$").unwrap();
     
-    let mut files_map: std::collections::HashMap<usize, (Option<String>, Option<String>)> = std::collections::HashMap::new();
+    // index -> (depotFile, action, rev)
+    type ShelvedEntry = (Option<String>, Option<String>, Option<String>);
+    let mut files_map: std::collections::HashMap<usize, ShelvedEntry> = std::collections::HashMap::new();
+    let mut time = None;
     
     for line in stdout.lines() {
         if let Some(cap) = line_re.captures(line) {
@@ -187,15 +291,22 @@ pub fn get_shelved_files(cl_number: &str) -> Result<Vec<OpenedFile>> {
                 index_str.parse::<usize>().unwrap_or(0)
             };
             
-            let entry = files_map.entry(index).or_insert((None, None));
+            let entry = files_map.entry(index).or_insert((None, None, None));
             
             match key {
+                "time" if index_str.is_empty() => {
+                    time = Some(val);
+                }
                 "depotFile" => {
                     entry.0 = Some(val);
                 }
                 "action" => {
                     entry.1 = Some(val);
                 }
+                "rev" => {
+                    // Revision the shelved file is based on, or the submitted revision
+                    entry.2 = Some(val);
+                }
                 _ => {}
             }
         }
@@ -207,19 +318,724 @@ pub fn get_shelved_files(cl_number: &str) -> Result<Vec<OpenedFile>> {
     indices.sort();
     
     for idx in indices {
-        if let Some((Some(file), Some(action))) = files_map.get(&idx) {
+        if let Some((Some(file), Some(action), rev)) = files_map.get(&idx) {
             files.push(OpenedFile {
                 changelist: cl_number.to_string(),
                 depot_file: file.clone(),
                 action: action.clone(),
-                workrev: None,
+                workrev: rev.clone(),
             });
         }
     }
     
+    Ok((files, time))
+}
+
+/// Get the raw content of a file revision (e.g. "//depot/a.c#have" or "//depot/a.c@=12345").
+pub fn print_file(file_spec: &str) -> Result<Vec<u8>> {
+    let output = Command::new("p4")
+        .arg("print")
+        .arg("-q")
+        .arg(file_spec)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("Failed to execute p4 print {}", file_spec))?;
+    
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() || stderr.contains("no such file") || stderr.contains("not on client") {
+        anyhow::bail!("Failed to print {}: {}", file_spec, stderr.trim());
+    }
+    
+    Ok(output.stdout)
+}
+
+#[derive(Debug, Clone)]
+pub struct OpenedFileState {
+    pub changelist: String,
+    pub depot_file: String,
+    pub have_rev: Option<String>,
+    pub head_rev: Option<String>,
+    pub unresolved: bool,
+}
+
+impl OpenedFileState {
+    /// A newer revision was submitted since this file was synced.
+    pub fn is_out_of_date(&self) -> bool {
+        matches!((&self.have_rev, &self.head_rev), (Some(have), Some(head)) if have != head)
+    }
+}
+
+/// Get have/head revisions and resolve state of all opened files in one call.
+pub fn get_opened_file_states() -> Result<Vec<OpenedFileState>> {
+    let stdout = run("p4", &["-ztag", "fstat", "-Ro", "-T", "depotFile,change,haveRev,headRev,unresolved", "//..."])?;
+    let line_re = Regex::new(r"^\.\.\.\s+(\w+)\s*(.*)$").unwrap();
+    
+    let mut out = Vec::new();
+    let mut current: Option<OpenedFileState> = None;
+    for line in stdout.lines() {
+        if let Some(cap) = line_re.captures(line) {
+            let val = cap[2].to_string();
+            match &cap[1] {
+                "depotFile" => {
+                    if let Some(state) = current.take() {
+                        out.push(state);
+                    }
+                    current = Some(OpenedFileState {
+                        changelist: "default".to_string(),
+                        depot_file: val,
+                        have_rev: None,
+                        head_rev: None,
+                        unresolved: false,
+                    });
+                }
+                key => {
+                    if let Some(state) = current.as_mut() {
+                        match key {
+                            "change" => state.changelist = val,
+                            "haveRev" => state.have_rev = Some(val),
+                            "headRev" => state.head_rev = Some(val),
+                            "unresolved" => state.unresolved = true,
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if let Some(state) = current {
+        out.push(state);
+    }
+    Ok(out)
+}
+
+/// Get the jobs attached to a changelist.
+pub fn get_fixes(cl_number: &str) -> Result<Vec<String>> {
+    let stdout = run("p4", &["-ztag", "fixes", "-c", cl_number])?;
+    let jobs = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("... Job "))
+        .map(|job| job.trim().to_string())
+        .collect();
+    Ok(jobs)
+}
+
+/// Split `p4 -ztag` output into its fields in order, e.g. "... rev0 3" gives ("rev0", "3").
+/// A multi-line description ("desc" or "desc0") continues on the untagged lines after it, up to
+/// the next tagged line, and is trimmed at the end.
+fn parse_ztag_fields(stdout: &str) -> Vec<(String, String)> {
+    let is_description = |key: &str| key.trim_end_matches(|c: char| c.is_ascii_digit()) == "desc";
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in stdout.lines() {
+        match line.strip_prefix("... ") {
+            Some(field) => {
+                let (key, value) = field.split_once(' ').unwrap_or((field, ""));
+                fields.push((key.to_string(), value.to_string()));
+            }
+            None => {
+                if let Some((_, value)) = fields.last_mut().filter(|(key, _)| is_description(key)) {
+                    value.push('\n');
+                    value.push_str(line);
+                }
+            }
+        }
+    }
+    for (key, value) in &mut fields {
+        if is_description(key) {
+            *value = value.trim_end().to_string();
+        }
+    }
+    fields
+}
+
+/// Format a p4 timestamp (seconds since the epoch) in local time, e.g. with "%Y/%m/%d".
+fn format_p4_time(secs: &str, format: &str) -> Option<String> {
+    let time = chrono::DateTime::from_timestamp(secs.parse::<i64>().ok()?, 0)?;
+    Some(time.with_timezone(&chrono::Local).format(format).to_string())
+}
+
+/// A submitted changelist in `p4 changes` output.
+#[derive(Debug, Clone)]
+pub struct SubmittedChange {
+    pub change: String,
+    pub user: String,
+    pub client: String,
+    pub date: String, // yyyy/mm/dd
+    pub description: String,
+}
+
+/// Get the most recent submitted changelists affecting a file spec, newest first. The spec may
+/// carry a revision range, e.g. "//depot/dir/...@2026/01/01,@now".
+pub fn get_changes(file_spec: &str, max: usize, user: Option<&str>) -> Result<Vec<SubmittedChange>> {
+    let max = max.to_string();
+    let mut args = vec!["-ztag", "changes", "-s", "submitted", "-l", "-m", &max];
+    if let Some(user) = user {
+        args.extend(["-u", user]);
+    }
+    args.push(file_spec);
+    let stdout = run("p4", &args)?;
+    
+    let mut changes: Vec<SubmittedChange> = Vec::new();
+    for (key, value) in parse_ztag_fields(&stdout) {
+        if key == "change" {
+            changes.push(SubmittedChange {
+                change: value,
+                user: String::new(),
+                client: String::new(),
+                date: String::new(),
+                description: String::new(),
+            });
+            continue;
+        }
+        let Some(change) = changes.last_mut() else {
+            continue;
+        };
+        match key.as_str() {
+            "user" => change.user = value,
+            "client" => change.client = value,
+            "time" => change.date = format_p4_time(&value, "%Y/%m/%d").unwrap_or(value),
+            "desc" => change.description = value,
+            _ => {}
+        }
+    }
+    
+    Ok(changes)
+}
+
+/// Get the date (yyyy/mm/dd) a pending changelist was last shelved, from `p4 describe -S`.
+pub fn get_shelf_date(cl_number: &str) -> Result<String> {
+    let stdout = run("p4", &["-ztag", "describe", "-S", "-s", "-m", "1", cl_number])?;
+    let time = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("... time "))
+        .ok_or_else(|| anyhow::anyhow!("No date for changelist {}", cl_number))?;
+    format_p4_time(time.trim(), "%Y/%m/%d").ok_or_else(|| anyhow::anyhow!("Invalid date for changelist {}: {}", cl_number, time))
+}
+
+/// Open a workspace file in a changelist with `p4 edit`, `p4 add` or `p4 delete`.
+pub fn open_file(action: &str, cl_number: &str, file: &str) -> Result<String> {
+    let output = Command::new("p4")
+        .args([action, "-c", cl_number, file])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("Failed to execute p4 {}", action))?;
+    
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() || !stderr.trim().is_empty() {
+        anyhow::bail!("{}", stderr.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Get the untagged `p4 describe -du` output of a submitted changelist: header, description,
+/// affected files and unified diffs.
+pub fn describe_change(cl_number: &str) -> Result<String> {
+    run("p4", &["describe", "-du", cl_number])
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeStatus {
+    Pending { client: String },
+    Submitted { change: String, client: String }, // number it was submitted as (may differ after renumbering)
+    Deleted,
+}
+
+/// Look up the status of several changelists with a single p4 call.
+/// Changelists the server says don't exist are reported as deleted; ones whose status couldn't be
+/// determined are missing from the result.
+pub fn get_change_statuses(cl_numbers: &[String]) -> Result<std::collections::HashMap<String, ChangeStatus>> {
+    let mut statuses = std::collections::HashMap::new();
+    if cl_numbers.is_empty() {
+        return Ok(statuses);
+    }
+    
+    // p4 changes can't select by CL number, but describe accepts a list of them
+    let output = Command::new("p4")
+        .arg("-ztag")
+        .arg("describe")
+        .arg("-s")
+        .args(cl_numbers)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .context("Failed to execute p4 describe")?;
+    
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    
+    // Unknown CLs only produce "no such changelist" errors; anything else means we can't tell
+    let other_errors = stderr
+        .lines()
+        .any(|l| !l.trim().is_empty() && !l.contains("no such changelist"));
+    if !output.status.success() && stdout.trim().is_empty() && other_errors {
+        anyhow::bail!("Failed to get changelist status: {}", stderr.trim());
+    }
+    
+    let line_re = Regex::new(r"^\.\.\.\s+(\w+)\s+(.*)$").unwrap();
+    
+    // (change, oldChange, status, client) of the current record
+    let mut record: (String, Option<String>, String, String) = Default::default();
+    let mut flush = |record: &mut (String, Option<String>, String, String)| {
+        if record.0.is_empty() {
+            return;
+        }
+        let (change, old_change, status, client) = std::mem::take(record);
+        let original = old_change.unwrap_or_else(|| change.clone());
+        let status = if status == "submitted" {
+            ChangeStatus::Submitted { change, client }
+        } else {
+            ChangeStatus::Pending { client }
+        };
+        statuses.insert(original, status);
+    };
+    
+    for line in stdout.lines() {
+        if let Some(cap) = line_re.captures(line) {
+            let val = cap[2].to_string();
+            match &cap[1] {
+                "change" => {
+                    flush(&mut record);
+                    record.0 = val;
+                }
+                "oldChange" => record.1 = Some(val),
+                "status" => record.2 = val,
+                "client" => record.3 = val,
+                _ => {}
+            }
+        }
+    }
+    flush(&mut record);
+    
+    // Only CLs the server explicitly doesn't know are deleted; others missing from the output (e.g.
+    // after a permission error) are left out, as their status is unknown
+    for line in stderr.lines() {
+        if let Some((cl, message)) = line.trim().split_once(" - ") {
+            if message.contains("no such changelist") && cl_numbers.iter().any(|c| c == cl) {
+                statuses.entry(cl.to_string()).or_insert(ChangeStatus::Deleted);
+            }
+        }
+    }
+    
+    Ok(statuses)
+}
+
+/// Shelve all opened files of a changelist. Returns p4's output.
+pub fn shelve_changelist(cl_number: &str) -> Result<String> {
+    // The -r flag replaces all shelved files, removing files no longer in the CL
+    run("p4", &["shelve", "-r", "-c", cl_number])
+}
+
+/// Delete all shelved files of a changelist
+pub fn delete_shelf(cl_number: &str) -> Result<()> {
+    run("p4", &["shelve", "-d", "-c", cl_number])?;
+    Ok(())
+}
+
+/// Delete an empty pending changelist.
+pub fn delete_changelist(cl_number: &str) -> Result<()> {
+    run("p4", &["change", "-d", cl_number])?;
+    Ok(())
+}
+
+/// Submit a pending changelist. Returns the number it was submitted as
+/// (the server renumbers changes that aren't the newest).
+pub fn submit_changelist(cl_number: &str) -> Result<String> {
+    let stdout = run("p4", &["submit", "-c", cl_number])?;
+    
+    // "Change 123 submitted." or "Change 123 renamed change 130 and submitted."
+    let re = Regex::new(r"Change (\d+) (?:renamed change (\d+) and )?submitted").unwrap();
+    match re.captures(&stdout) {
+        Some(cap) => Ok(cap.get(2).unwrap_or_else(|| cap.get(1).unwrap()).as_str().to_string()),
+        None => anyhow::bail!("Failed to parse submitted CL number from: {}", stdout.trim()),
+    }
+}
+
+/// A file waiting to be resolved, from `p4 resolve -n`
+#[derive(Debug, Clone, Default)]
+pub struct PendingResolve {
+    pub client_file: String,    // local path
+    pub from_file: String,      // depot path of "theirs"
+    pub start_from_rev: String, // "3", "none", or "@=123" for unshelves
+    pub end_from_rev: String,
+    pub base_file: Option<String>,
+    pub base_rev: Option<String>,
+    pub resolve_type: String,   // content, filetype, move, ...
+}
+
+impl PendingResolve {
+    fn file_spec(file: &str, rev: &str) -> String {
+        if rev.starts_with('@') || rev.starts_with('#') {
+            format!("{}{}", file, rev)
+        } else {
+            format!("{}#{}", file, rev)
+        }
+    }
+    
+    /// File spec of their version
+    pub fn theirs_spec(&self) -> String {
+        Self::file_spec(&self.from_file, &self.end_from_rev)
+    }
+    
+    /// File spec of the common base, if there is one
+    pub fn base_spec(&self) -> Option<String> {
+        match (&self.base_file, &self.base_rev) {
+            (Some(file), Some(rev)) if rev != "none" => Some(Self::file_spec(file, rev)),
+            _ if self.start_from_rev != "none" => Some(Self::file_spec(&self.from_file, &self.start_from_rev)),
+            _ => None,
+        }
+    }
+}
+
+/// Get files needing resolve, optionally limited to `paths`
+pub fn get_pending_resolves(paths: &[String]) -> Result<Vec<PendingResolve>> {
+    let mut args = vec!["-ztag", "resolve", "-n"];
+    args.extend(paths.iter().map(|p| p.as_str()));
+    let output = Command::new("p4")
+        .args(&args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .context("Failed to execute p4 resolve -n")?;
+    
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("no file(s) to resolve") {
+        return Ok(Vec::new());
+    }
+    if !output.status.success() {
+        anyhow::bail!("p4 resolve -n failed: {}", stderr.trim());
+    }
+    
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line_re = Regex::new(r"^\.\.\.\s+(\w+)\s+(.*)$").unwrap();
+    let mut resolves = Vec::new();
+    let mut current = PendingResolve::default();
+    
+    for line in stdout.lines() {
+        if line.trim().is_empty() {
+            if !current.client_file.is_empty() {
+                resolves.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        if let Some(cap) = line_re.captures(line) {
+            let value = cap[2].trim().to_string();
+            match &cap[1] {
+                "clientFile" => {
+                    // A new record can start without a blank line in between
+                    if !current.client_file.is_empty() {
+                        resolves.push(std::mem::take(&mut current));
+                    }
+                    current.client_file = value;
+                }
+                "fromFile" => current.from_file = value,
+                "startFromRev" => current.start_from_rev = value,
+                "endFromRev" => current.end_from_rev = value,
+                "baseFile" => current.base_file = Some(value),
+                "baseRev" => current.base_rev = Some(value),
+                "resolveType" => current.resolve_type = value,
+                _ => {}
+            }
+        }
+    }
+    if !current.client_file.is_empty() {
+        resolves.push(current);
+    }
+    
+    Ok(resolves)
+}
+
+/// Resolve a file automatically: mode is "y" (accept yours), "t" (accept theirs) or "m" (accept merge).
+/// Returns p4's output.
+pub fn resolve_file(file: &str, mode: &str) -> Result<String> {
+    let flag = format!("-a{}", mode);
+    let output = Command::new("p4")
+        .args(["resolve", &flag, file])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("Failed to execute p4 resolve {}", flag))?;
+    
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() || !stderr.trim().is_empty() {
+        anyhow::bail!("p4 resolve {} failed: {}", flag, stderr.trim());
+    }
+    // -am leaves files with conflicts unresolved
+    if stdout.contains("resolve skipped") {
+        anyhow::bail!("{} has conflicts, resolve skipped", file);
+    }
+    Ok(stdout)
+}
+
+/// A file `p4 sync` would change
+#[derive(Debug, Clone, Default)]
+pub struct SyncPreview {
+    pub depot_file: String,
+    pub client_file: String,
+    pub rev: String,
+    pub action: String, // added, updated, deleted, ...
+}
+
+/// The newest submitted change on the server, to pin a sync to what was previewed.
+pub fn get_head_change() -> Result<String> {
+    let stdout = run("p4", &["-ztag", "changes", "-m", "1", "-s", "submitted"])?;
+    stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("... change "))
+        .map(|change| change.trim().to_string())
+        .context("No submitted changes on the server")
+}
+
+/// Preview what `p4 sync` would do for `paths` (the whole client if empty)
+pub fn preview_sync(paths: &[String]) -> Result<Vec<SyncPreview>> {
+    let mut args = vec!["-ztag", "sync", "-n"];
+    args.extend(paths.iter().map(|p| p.as_str()));
+    let output = Command::new("p4")
+        .args(&args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .context("Failed to execute p4 sync -n")?;
+    
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() && !stderr.contains("up-to-date") {
+        anyhow::bail!("p4 sync -n failed: {}", stderr.trim());
+    }
+    
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line_re = Regex::new(r"^\.\.\.\s+(\w+)\s+(.*)$").unwrap();
+    let mut files = Vec::new();
+    let mut current = SyncPreview::default();
+    
+    for line in stdout.lines() {
+        if let Some(cap) = line_re.captures(line) {
+            let value = cap[2].trim().to_string();
+            match &cap[1] {
+                "depotFile" => {
+                    if !current.depot_file.is_empty() {
+                        files.push(std::mem::take(&mut current));
+                    }
+                    current.depot_file = value;
+                }
+                "clientFile" => current.client_file = value,
+                "rev" => current.rev = value,
+                "action" => current.action = value,
+                _ => {}
+            }
+        }
+    }
+    if !current.depot_file.is_empty() {
+        files.push(current);
+    }
+    
     Ok(files)
 }
 
+/// Sync `paths` (the whole client if empty), calling `on_line` for each line p4 prints
+/// (one per file). Returns the error lines.
+pub fn sync_with_progress(paths: &[String], mut on_line: impl FnMut(&str)) -> Result<Vec<String>> {
+    use std::io::BufRead;
+    
+    let mut child = Command::new("p4")
+        .arg("sync")
+        .args(paths)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to spawn p4 sync")?;
+    
+    // Read stderr on a separate thread so a chatty p4 can't block on a full pipe
+    let stderr = child.stderr.take().unwrap();
+    let stderr_thread = std::thread::spawn(move || {
+        std::io::BufReader::new(stderr)
+            .lines()
+            .map_while(|l| l.ok())
+            .filter(|l| !l.trim().is_empty() && !l.contains("up-to-date"))
+            .collect::<Vec<String>>()
+    });
+    
+    for line in std::io::BufReader::new(child.stdout.take().unwrap()).lines() {
+        on_line(&line?);
+    }
+    
+    let status = child.wait()?;
+    let errors = stderr_thread.join().unwrap_or_default();
+    if !status.success() {
+        anyhow::bail!("p4 sync failed ({}): {}", status, errors.join("\n"));
+    }
+    Ok(errors)
+}
+
+/// Find files under `path` that were changed without being opened, with `p4 reconcile -n`.
+/// The action says what reconcile would open them for: edit (modified), add (new) or delete (missing).
+/// `changelist` is left empty.
+pub fn reconcile_preview(path: &str) -> Result<Vec<OpenedFile>> {
+    let output = Command::new("p4")
+        .args(["-ztag", "reconcile", "-n", path])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .context("Failed to execute p4 reconcile -n")?;
+    
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("no file(s) to reconcile") {
+        return Ok(Vec::new());
+    }
+    if !output.status.success() {
+        anyhow::bail!("p4 reconcile -n failed: {}", stderr.trim());
+    }
+    
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line_re = Regex::new(r"^\.\.\.\s+(\w+)\s+(.*)$").unwrap();
+    let mut files = Vec::new();
+    let mut current: Option<OpenedFile> = None;
+    
+    for line in stdout.lines() {
+        if let Some(cap) = line_re.captures(line) {
+            let value = cap[2].trim().to_string();
+            match &cap[1] {
+                "depotFile" => {
+                    files.extend(current.take());
+                    current = Some(OpenedFile {
+                        changelist: String::new(),
+                        depot_file: value,
+                        action: String::new(),
+                        workrev: None,
+                    });
+                }
+                "action" => {
+                    if let Some(f) = current.as_mut() {
+                        f.action = value;
+                    }
+                }
+                "workRev" => {
+                    if let Some(f) = current.as_mut() {
+                        f.workrev = Some(value);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    files.extend(current);
+    
+    Ok(files)
+}
+
+/// Open a changed file in a changelist with `p4 reconcile`, which picks edit, add or delete.
+pub fn reconcile_file(cl_number: &str, file: &str) -> Result<String> {
+    let output = Command::new("p4")
+        .args(["reconcile", "-c", cl_number, file])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .context("Failed to execute p4 reconcile")?;
+    
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() || !stderr.trim().is_empty() {
+        anyhow::bail!("{}", stderr.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Depot paths of opened files whose content is identical to the have revision. Besides unchanged
+/// edits, this includes files opened for integrate, branch or move/add without content changes.
+pub fn get_unchanged_files() -> Result<std::collections::HashSet<String>> {
+    let output = Command::new("p4")
+        .args(["-ztag", "diff", "-sr"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .context("Failed to execute p4 diff -sr")?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("not opened") || stderr.contains("no file(s)") {
+        return Ok(std::collections::HashSet::new());
+    }
+    if !output.status.success() {
+        anyhow::bail!("p4 diff -sr failed: {}", stderr.trim());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line_re = Regex::new(r"^\.\.\.\s+(\w+)\s+(.*)$").unwrap();
+    let mut depot_files = std::collections::HashSet::new();
+    let mut client_files = Vec::new();
+
+    for line in stdout.lines() {
+        if let Some(cap) = line_re.captures(line) {
+            match &cap[1] {
+                "depotFile" => { depot_files.insert(cap[2].trim().to_string()); }
+                "clientFile" => client_files.push(cap[2].trim().to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    // Some servers only report local paths for diff -s
+    if depot_files.is_empty() {
+        for client_file in client_files {
+            if let Some(depot) = get_depot_path(&client_file)? {
+                depot_files.insert(depot);
+            }
+        }
+    }
+    Ok(depot_files)
+}
+
+/// Get a unified diff of opened files against the revisions they were opened at.
+pub fn get_diff(files: &[String]) -> Result<String> {
+    if files.is_empty() {
+        return Ok(String::new());
+    }
+    let mut args = vec!["diff", "-du"];
+    args.extend(files.iter().map(|f| f.as_str()));
+    run("p4", &args)
+}
+
+/// Get (added, deleted) line counts for each opened file, keyed by depot path.
+/// Files without content changes (e.g. adds) are not included.
+pub fn get_diff_stats(files: &[String]) -> Result<std::collections::HashMap<String, (usize, usize)>> {
+    let mut stats = std::collections::HashMap::new();
+    if files.is_empty() {
+        return Ok(stats);
+    }
+    
+    let mut args = vec!["diff", "-ds"];
+    args.extend(files.iter().map(|f| f.as_str()));
+    let stdout = run("p4", &args)?;
+    
+    // ==== //depot/a.c#3 - /ws/a.c ====
+    // add 1 chunks 2 lines
+    // deleted 0 chunks 0 lines
+    // changed 1 chunks 3 / 4 lines
+    let header_re = Regex::new(r"^==== (//[^#]+)#").unwrap();
+    let add_del_re = Regex::new(r"^(add|deleted) \d+ chunks (\d+) lines").unwrap();
+    let changed_re = Regex::new(r"^changed \d+ chunks (\d+) / (\d+) lines").unwrap();
+    
+    let mut current: Option<String> = None;
+    for line in stdout.lines() {
+        if let Some(cap) = header_re.captures(line) {
+            current = Some(cap[1].to_string());
+            stats.insert(cap[1].to_string(), (0, 0));
+        } else if let Some(entry) = current.as_ref().and_then(|f| stats.get_mut(f)) {
+            if let Some(cap) = add_del_re.captures(line) {
+                let count: usize = cap[2].parse().unwrap_or(0);
+                if &cap[1] == "add" {
+                    entry.0 += count;
+                } else {
+                    entry.1 += count;
+                }
+            } else if let Some(cap) = changed_re.captures(line) {
+                entry.1 += cap[1].parse::<usize>().unwrap_or(0);
+                entry.0 += cap[2].parse::<usize>().unwrap_or(0);
+            }
+        }
+    }
+    
+    Ok(stats)
+}
+
 /// Unshelve files from a changelist
 pub fn unshelve_changelist(cl_number: &str) -> Result<()> {
     let output = Command::new("p4")
@@ -319,7 +1135,39 @@ pub fn get_current_client() -> Result<String> {
     anyhow::bail!("Could not determine current client")
 }
 
-/// Get the depot path for a local file using p4 where
+/// Get the server address (P4PORT) in effect for the current directory
+pub fn get_port() -> Result<String> {
+    let stdout = run("p4", &["set", "-q", "P4PORT"])?;
+    for line in stdout.lines() {
+        if let Some(port) = line.strip_prefix("P4PORT=") {
+            return Ok(port.trim().to_string());
+        }
+    }
+    // p4's built-in default when nothing is set
+    Ok("perforce:1666".to_string())
+}
+
+/// Get the Perforce user name (P4USER), falling back to the login name
+pub fn get_user() -> Result<String> {
+    let stdout = run("p4", &["set", "-q", "P4USER"])?;
+    for line in stdout.lines() {
+        if let Some(user) = line.strip_prefix("P4USER=") {
+            return Ok(user.trim().to_string());
+        }
+    }
+    Ok(std::env::var("USER").unwrap_or_default())
+}
+
+/// Get the stream the current client is bound to, if any
+pub fn get_client_stream() -> Result<Option<String>> {
+    let stdout = run("p4", &["client", "-o"])?;
+    Ok(stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("Stream:"))
+        .map(|stream| stream.trim().to_string()))
+}
+
+/// Get the depot path for a local file using p4 where. Returns None for files outside the client view.
 pub fn get_depot_path(local_path: &str) -> Result<Option<String>> {
     // Try to canonicalize the path first (resolve relative paths, symlinks, etc.)
     let resolved_path = std::fs::canonicalize(local_path)
@@ -339,17 +1187,12 @@ pub fn get_depot_path(local_path: &str) -> Result<Option<String>> {
     let stdout = String::from_utf8_lossy(&output.stdout);
     
     // Check for common error messages
-    if stderr.contains("not in client view") || stderr.contains("file(s) not in client view") {
-        eprintln!("Debug: File is not in the Perforce client view");
-        eprintln!("Debug: stderr: {}", stderr.trim());
+    if stderr.contains("not in client view") || stderr.contains("not under client's root") {
         return Ok(None);
     }
     
     if !output.status.success() {
-        eprintln!("Debug: p4 where failed");
-        eprintln!("Debug: stderr: {}", stderr.trim());
-        eprintln!("Debug: stdout: {}", stdout.trim());
-        return Ok(None);
+        anyhow::bail!("p4 where {} failed: {}", path_str, stderr.trim());
     }
     
     // p4 where output format: depot_path client_path local_path
@@ -362,8 +1205,6 @@ pub fn get_depot_path(local_path: &str) -> Result<Option<String>> {
         }
     }
     
-    eprintln!("Debug: Could not parse depot path from p4 where output");
-    eprintln!("Debug: stdout: {}", stdout.trim());
     Ok(None)
 }
 
@@ -400,22 +1241,149 @@ pub fn get_local_path(depot_path: &str) -> Result<Option<String>> {
     Ok(None)
 }
 
+/// One revision in the history of a file.
+#[derive(Debug, Clone)]
+pub struct FileRevision {
+    pub depot_file: String,
+    pub rev: String,
+    pub change: String,
+    pub action: String,
+    pub user: String,
+    pub date: String, // yyyy/mm/dd
+    pub description: String,
+}
+
+/// Get the history of a file, newest first, following branches and integrations (`p4 filelog -i`).
+/// Revisions of the files it was branched from follow those of the file itself.
+pub fn get_filelog(file_spec: &str) -> Result<Vec<FileRevision>> {
+    let stdout = run("p4", &["-ztag", "filelog", "-i", "-l", file_spec])?;
+    // Per-revision fields carry the revision's index: "rev0", "change0". Integration records
+    // ("how0,0") don't match and are skipped.
+    let key_re = Regex::new(r"^([a-zA-Z]+)(\d*)$").unwrap();
+    
+    let mut revisions: Vec<FileRevision> = Vec::new();
+    let mut depot_file = String::new();
+    let mut first_of_file = 0; // index in `revisions` of revision 0 of the current depot file
+    
+    for (key, value) in parse_ztag_fields(&stdout) {
+        let Some(cap) = key_re.captures(&key) else {
+            continue;
+        };
+        if &cap[1] == "depotFile" {
+            depot_file = value;
+            first_of_file = revisions.len();
+            continue;
+        }
+        let Ok(idx) = cap[2].parse::<usize>() else {
+            continue;
+        };
+        while revisions.len() <= first_of_file + idx {
+            revisions.push(FileRevision {
+                depot_file: depot_file.clone(),
+                rev: String::new(),
+                change: String::new(),
+                action: String::new(),
+                user: String::new(),
+                date: String::new(),
+                description: String::new(),
+            });
+        }
+        let revision = &mut revisions[first_of_file + idx];
+        match &cap[1] {
+            "rev" => revision.rev = value,
+            "change" => revision.change = value,
+            "action" => revision.action = value,
+            "user" => revision.user = value,
+            "time" => revision.date = format_p4_time(&value, "%Y/%m/%d").unwrap_or(value),
+            "desc" => revision.description = value,
+            _ => {}
+        }
+    }
+    
+    Ok(revisions)
+}
+
+/// Get a unified diff between two file revisions, e.g. "//depot/a.c#2" and "//depot/a.c#5".
+pub fn get_diff2(from_spec: &str, to_spec: &str) -> Result<String> {
+    run("p4", &["diff2", "-du", from_spec, to_spec])
+}
+
 #[derive(Debug, Clone)]
 pub struct AnnotateLine {
     pub cl_number: String,
     pub username: String,
     pub date: String,
     pub line_content: String,
+    pub present: bool, // false for lines -a lists that were deleted before the annotated revision
+}
+
+/// How `get_annotate` attributes lines.
+#[derive(Debug, Clone, Copy)]
+pub struct AnnotateOptions {
+    pub follow_integrations: bool, // -I: attribute lines to the CL that made them in the source branch
+    pub ignore_space_change: bool, // -db: ignore whitespace changes
+    pub ignore_all_space: bool,    // -dw: ignore all whitespace
 }
 
-/// Get annotate information for a file
-pub fn get_annotate(file_path: &str) -> Result<Vec<AnnotateLine>> {
-    // Use -a -u flags: -a shows changelist ranges, -u adds user and date
-    // Use -c to show changelist numbers instead of revision numbers
-    // Use -I to follow all integrations
-    let stdout = run("p4", &["annotate", "-a", "-u", "-c", "-I", "-q", file_path])?;
+impl Default for AnnotateOptions {
+    fn default() -> Self {
+        AnnotateOptions { follow_integrations: true, ignore_space_change: false, ignore_all_space: false }
+    }
+}
+
+/// Get annotate information for a file spec (a path, optionally with a #rev or @change/label/date).
+/// With `with_deleted`, lines deleted before that revision are included too (not `present`), at the
+/// cost of a second, `-a` annotate on the server.
+pub fn get_annotate(file_spec: &str, options: &AnnotateOptions, with_deleted: bool) -> Result<Vec<AnnotateLine>> {
+    // Use -u to add user and date, -c to show changelist numbers instead of revision numbers,
+    // -I to follow all integrations (slow on heavily integrated files)
+    let mut args = vec!["annotate", "-u", "-c", "-q"];
+    if options.follow_integrations {
+        args.push("-I");
+    }
+    if options.ignore_all_space {
+        args.push("-dw");
+    } else if options.ignore_space_change {
+        args.push("-db");
+    }
+    args.push(file_spec);
+    
+    if !with_deleted {
+        let mut lines = parse_annotate(&run("p4", &args)?);
+        for line in &mut lines {
+            line.present = true;
+        }
+        return Ok(lines);
+    }
+    
+    // -a adds deleted lines with the range of changes they existed in; without it p4 lists exactly the
+    // lines of the revision. Both are needed to tell which lines are still there, so run them together.
+    let all_args: Vec<&str> = args[..1].iter().chain(&["-a"]).chain(&args[1..]).copied().collect();
+    let (all, present) = std::thread::scope(|scope| {
+        let all = scope.spawn(|| run("p4", &all_args));
+        let present = run("p4", &args);
+        (all.join().unwrap_or_else(|_| Err(anyhow!("p4 annotate -a panicked"))), present)
+    });
+    let mut lines = parse_annotate(&all?);
+    let present = parse_annotate(&present?);
+    
+    // Present lines are the ones -a lists with the same first change and content, in the same order
+    let key = |l: &AnnotateLine| format!("{}\t{}", l.cl_number.split('-').next().unwrap_or(""), l.line_content);
+    let all_keys: Vec<String> = lines.iter().map(key).collect();
+    let present_keys: Vec<String> = present.iter().map(key).collect();
+    for op in similar::capture_diff_slices(similar::Algorithm::Myers, &all_keys, &present_keys) {
+        if let similar::DiffOp::Equal { old_index, len, .. } = op {
+            for line in &mut lines[old_index..old_index + len] {
+                line.present = true;
+            }
+        }
+    }
     
-    // Format with -a -u flags: <cl-range>: <user> <date> <line>
+    Ok(lines)
+}
+
+fn parse_annotate(stdout: &str) -> Vec<AnnotateLine> {
+    // Format: <cl or cl-range>: <user> <date> <line>
     // Important: Use single space after date to preserve indentation in line content
     let line_re = Regex::new(r"^(\d+(?:-\d+)?):\s+(\S+)\s+(\d{4}/\d{2}/\d{2}) (.*)$").unwrap();
     
@@ -427,6 +1395,7 @@ pub fn get_annotate(file_path: &str) -> Result<Vec<AnnotateLine>> {
                 username: cap[2].to_string(),
                 date: cap[3].to_string(),
                 line_content: cap[4].to_string(),
+                present: false,
             });
         } else {
             // If the line doesn't match, it might be a continuation or malformed
@@ -435,9 +1404,9 @@ pub fn get_annotate(file_path: &str) -> Result<Vec<AnnotateLine>> {
                 username: "?".to_string(),
                 date: "?".to_string(),
                 line_content: line.to_string(),
+                present: false,
             });
         }
     }
-    
-    Ok(lines)
+    lines
 }